@@ -1,4 +1,8 @@
+use crate::config::RunConfig;
 use crate::population::Population;
+use crate::race::Race;
+use crate::replay::{Recording, ReplayViewer};
+use crate::track::Track;
 use crate::{
     ui::{Button, Slider},
     WINDOW_HEIGHT, WINDOW_WIDTH,
@@ -9,101 +13,238 @@ use macroquad::prelude::*;
 enum ProgramStatus {
     MainMenu,
     Simulation,
+    Replay,
+    Race,
 }
 
 pub struct App {
     status: ProgramStatus,
     population: Option<Population>,
+    replay: Option<ReplayViewer>,
+    race: Option<Race>,
 
     // ui elements
     buttons: Vec<Button>,
     sliders: Vec<Slider>,
     end_button: Option<Button>, // new line
+
+    // track selection
+    track_names: Vec<String>,
+    selected_track: usize,
+
+    // settings from racers.cfg and the command line
+    config: RunConfig,
 }
 
 impl App {
     pub fn new() -> Self {
+        let track_names = Track::available_tracks();
+
+        // use the seed from the config if there is one so runs can be repeated
+        let mut config = RunConfig::load();
+        if config.seed == 0 {
+            config.seed = macroquad::miniquad::date::now() as u64;
+        }
+        macroquad::rand::srand(config.seed);
+        let optimiser_label = config.optimiser.label().to_string();
+
         Self {
             status: ProgramStatus::MainMenu,
             population: None,
+            replay: None,
+            race: None,
 
             // ui design
-            buttons: vec![Button::new(
-                400.0,
-                550.0,
-                400.0,
-                150.0,
-                "Run".to_string(),
-                LIGHTGRAY,
-            )],
+            buttons: vec![
+                Button::new(400.0, 550.0, 400.0, 150.0, "Run".to_string(), LIGHTGRAY),
+                Button::new(850.0, 550.0, 300.0, 70.0, track_names[0].clone(), LIGHTGRAY),
+                Button::new(850.0, 630.0, 300.0, 70.0, "Drive".to_string(), SKYBLUE),
+                Button::new(
+                    850.0,
+                    710.0,
+                    300.0,
+                    70.0,
+                    "Drive vs AI".to_string(),
+                    SKYBLUE,
+                ),
+                Button::new(50.0, 550.0, 300.0, 70.0, "Replay".to_string(), LIGHTGRAY),
+                Button::new(50.0, 630.0, 300.0, 70.0, "Race".to_string(), LIGHTGRAY),
+                Button::new(50.0, 710.0, 300.0, 70.0, optimiser_label, LIGHTGRAY),
+            ],
 
             end_button: None, // set to none by default
 
             sliders: vec![
                 Slider::new(600.0, 350.0, 10, 300, 220),
                 Slider::new(600.0, 450.0, 500, 3000, 1250),
+                Slider::new(600.0, 250.0, 1, 10, 1),
             ],
+
+            track_names,
+            selected_track: 0,
+
+            config,
         }
     }
 
     pub fn update(&mut self) {
-        if self.status == ProgramStatus::MainMenu {
-            // updates for main menu instance
-            for b in &mut self.buttons {
-                b.check_pressed();
+        match self.status {
+            ProgramStatus::MainMenu => self.update_menu(),
+            ProgramStatus::Simulation => {
+                // run the simulation
+                if let Some(pop) = &mut self.population {
+                    pop.update();
+                }
+                self.update_end_button();
             }
-            for s in &mut self.sliders {
-                s.update();
+            ProgramStatus::Replay => {
+                if let Some(replay) = &mut self.replay {
+                    replay.update();
+                }
+                self.update_end_button();
             }
+            ProgramStatus::Race => {
+                if let Some(race) = &mut self.race {
+                    race.update();
+                }
+                self.update_end_button();
+            }
+        }
+    }
+
+    fn update_menu(&mut self) {
+        // updates for main menu instance
+        for b in &mut self.buttons {
+            b.check_pressed();
+        }
+        for s in &mut self.sliders {
+            s.update();
+        }
 
-            // if the first button is pressed, (aka the 'Run' button)
-            if self.buttons[0].pressed {
-                self.status = ProgramStatus::Simulation;
-                // create population
-                let pop_size = self.sliders[0].value;
-                let gen_length = self.sliders[1].value;
-
-                self.population = Some(Population::new(pop_size as usize, gen_length as u32));
-
-                // create the end simulation button
-                // button width is 150px height is 75px
-                self.end_button = Some(Button::new(
-                    WINDOW_WIDTH as f32 - 150.0,
-                    WINDOW_HEIGHT as f32 - 150.0, // shift up 150px from bottom rather than 75px
-                    150.0,
-                    75.0,
-                    "End".to_string(),
-                    RED,
-                ));
-
-                // reset the UI components
-                for b in self.buttons.iter_mut() {
-                    b.reset();
+        // the track button cycles through the available tracks
+        if self.buttons[1].pressed {
+            self.selected_track = (self.selected_track + 1) % self.track_names.len();
+            let name = self.track_names[self.selected_track].clone();
+            self.buttons[1].set_text(name);
+            self.buttons[1].reset();
+        }
+
+        // the optimiser button cycles through the ways of making each generation
+        if self.buttons[6].pressed {
+            self.config.optimiser = self.config.optimiser.next();
+            let label = self.config.optimiser.label().to_string();
+            self.buttons[6].set_text(label);
+            self.buttons[6].reset();
+        }
+
+        // the replay button watches the best lap saved for the selected track
+        if self.buttons[4].pressed {
+            self.buttons[4].reset();
+            // with the smoothing and vehicle model picked in the menu
+            let name = &self.track_names[self.selected_track];
+            let smoothing = self.sliders[2].value as usize;
+            let path = Recording::path_for(name, smoothing, self.config.vehicle_model.name());
+            let viewer = Recording::load(&path).and_then(ReplayViewer::new);
+            match viewer {
+                Ok(viewer) => {
+                    self.replay = Some(viewer);
+                    self.status = ProgramStatus::Replay;
+                    self.create_end_button();
                 }
-                for s in self.sliders.iter_mut() {
-                    s.reset();
+                Err(e) => println!("Could not load replay - {}", e),
+            }
+            return;
+        }
+
+        // the race button lines up the saved brains on the grid
+        if self.buttons[5].pressed {
+            self.buttons[5].reset();
+            let track = Track::by_name(&self.track_names[self.selected_track]);
+            let race = track.and_then(|track| {
+                let track = track.smoothed(self.sliders[2].value as usize);
+                Race::new(track, self.config.clone())
+            });
+            match race {
+                Ok(race) => {
+                    self.race = Some(race);
+                    self.status = ProgramStatus::Race;
+                    self.create_end_button();
                 }
+                Err(e) => println!("Could not start race - {}", e),
             }
-        } else {
-            // run the simulation
+            return;
+        }
+
+        // the 'Run' button evolves the ai, the 'Drive' buttons add a human driver
+        // driving alone means no ai cars at all
+        let human = self.buttons[2].pressed || self.buttons[3].pressed;
+        if self.buttons[0].pressed || human {
+            // load the selected track, stay in the menu if it can't be read
+            let track = match Track::by_name(&self.track_names[self.selected_track]) {
+                Ok(track) => track.smoothed(self.sliders[2].value as usize),
+                Err(e) => {
+                    println!("Could not load track - {}", e);
+                    for b in self.buttons.iter_mut() {
+                        b.reset();
+                    }
+                    return;
+                }
+            };
+
+            self.status = ProgramStatus::Simulation;
+            // create population
+            let mut pop_size = self.sliders[0].value;
+            if self.buttons[2].pressed {
+                pop_size = 0;
+            }
+            let gen_length = self.sliders[1].value;
 
-            if let Some(pop) = &mut self.population {
-                pop.update();
+            self.population = Some(Population::new(
+                pop_size as usize,
+                gen_length as u32,
+                track,
+                self.config.clone(),
+                human,
+            ));
+
+            self.create_end_button();
+
+            // reset the UI components
+            for b in self.buttons.iter_mut() {
+                b.reset();
+            }
+            for s in self.sliders.iter_mut() {
+                s.reset();
             }
+        }
+    }
 
-            // update the end button
-            if let Some(end_button) = &mut self.end_button {
-                end_button.check_pressed();
+    fn create_end_button(&mut self) {
+        // button width is 150px height is 75px
+        self.end_button = Some(Button::new(
+            WINDOW_WIDTH as f32 - 150.0,
+            WINDOW_HEIGHT as f32 - 150.0, // shift up 150px from bottom rather than 75px
+            150.0,
+            75.0,
+            "End".to_string(),
+            RED,
+        ));
+    }
 
-                if end_button.pressed {
-                    self.population = None;
-                    self.status = ProgramStatus::MainMenu;
+    fn update_end_button(&mut self) {
+        if let Some(end_button) = &mut self.end_button {
+            end_button.check_pressed();
 
-                    // reset the ui components
-                    self.end_button.as_mut().unwrap().reset();
+            if end_button.pressed {
+                self.population = None;
+                self.replay = None;
+                self.race = None;
+                self.status = ProgramStatus::MainMenu;
 
-                    self.end_button = None;
-                }
+                // reset the ui components
+                self.end_button.as_mut().unwrap().reset();
+
+                self.end_button = None;
             }
         }
     }
@@ -124,13 +265,22 @@ impl App {
             draw_text("Population Size:", 100.0, 350.0, 30.0, BLACK);
             draw_text("Generation Time Limit:", 100.0, 450.0, 30.0, BLACK);
             draw_text("(Ticks)", 140.0, 475.0, 18.0, BLACK);
+            draw_text("Track Smoothing:", 100.0, 250.0, 30.0, BLACK);
+            draw_text("(Points per segment)", 140.0, 275.0, 18.0, BLACK);
+            draw_text("Track:", 850.0, 540.0, 30.0, BLACK);
             draw_text("Create Simulation", 300.0, 150.0, 75.0, BLACK);
         } else {
-            // inside a simulation so draw it!
+            // inside a simulation, replay or race so draw it!
 
             if let Some(pop) = &self.population {
                 pop.draw();
             }
+            if let Some(replay) = &self.replay {
+                replay.draw();
+            }
+            if let Some(race) = &self.race {
+                race.draw();
+            }
 
             // draw the end button
             if let Some(end_button) = &self.end_button {
@@ -0,0 +1,352 @@
+use crate::config::RunConfig;
+use crate::controller::NETWORK_OUTPUTS;
+use crate::network::{Activation, Layer, Network};
+use crate::sensors::SensorConfig;
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+
+pub const BRAINS_FOLDER: &str = "brains";
+pub const BRAIN_EXTENSION: &str = "brain";
+
+// a layer part way through being read in
+struct PartialLayer {
+    inputs: usize,
+    outputs: usize,
+    activation: Option<Activation>,
+    recurrent: bool,
+    weights: Vec<Vec<f64>>,
+    recurrent_weights: Vec<Vec<f64>>,
+}
+
+// a trained network saved to disk with where it came from
+pub struct SavedBrain {
+    pub name: String,
+    pub track_name: String,
+    pub generation: usize,
+    pub fitness: f64,
+    pub settings: Vec<(String, String)>, // the run config it was trained with
+    pub network: Network,
+}
+
+impl SavedBrain {
+    pub fn path_for(name: &str) -> String {
+        return format!("{}/{}.{}", BRAINS_FOLDER, name, BRAIN_EXTENSION);
+    }
+
+    pub fn available() -> Vec<String> {
+        // names of every brain in the brains folder, sorted
+        let mut names = vec![];
+        if let Ok(entries) = fs::read_dir(BRAINS_FOLDER) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_some_and(|e| e == BRAIN_EXTENSION) {
+                    if let Some(stem) = path.file_stem() {
+                        names.push(stem.to_string_lossy().to_string());
+                    }
+                }
+            }
+        }
+        names.sort();
+        return names;
+    }
+
+    pub fn by_name(name: &str) -> Result<Self, String> {
+        let mut brain = Self::load(&Self::path_for(name))?;
+        brain.name = name.to_string();
+        return Ok(brain);
+    }
+
+    pub fn sensors(&self) -> SensorConfig {
+        // the sensors it was trained with, brains saved before they could be changed
+        // don't have them so get the defaults
+        let mut config = RunConfig::default();
+        for (key, value) in self.settings.iter() {
+            config.set(key, value);
+        }
+        return config.sensors;
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        fs::create_dir_all(BRAINS_FOLDER)?;
+        return self.save_to(&Self::path_for(&self.name));
+    }
+
+    pub fn save_to(&self, path: &str) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+
+        writeln!(file, "# racers brain")?;
+        writeln!(file, "track = {}", self.track_name)?;
+        writeln!(file, "generation = {}", self.generation)?;
+        writeln!(file, "fitness = {}", self.fitness)?;
+        for (key, value) in self.settings.iter() {
+            writeln!(file, "setting {} = {}", key, value)?;
+        }
+
+        // each layer is its size, a line of weights per output and then the biases
+        // recurrent layers have a line of memory weights per output before the biases
+        for layer in self.network.layers.iter() {
+            let mut kind = "";
+            if layer.recurrent_weights.is_some() {
+                kind = ", recurrent";
+            }
+            writeln!(
+                file,
+                "layer = {}, {}, {}{}",
+                layer.weights[0].len(),
+                layer.bias.len(),
+                Activation::name(layer.activation),
+                kind
+            )?;
+            for row in layer.weights.iter() {
+                writeln!(file, "{}", join(row))?;
+            }
+            if let Some(recurrent_weights) = &layer.recurrent_weights {
+                for row in recurrent_weights.iter() {
+                    writeln!(file, "{}", join(row))?;
+                }
+            }
+            writeln!(file, "{}", join(&layer.bias))?;
+        }
+
+        return Ok(());
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+        let mut brain = Self {
+            name: String::new(),
+            track_name: String::new(),
+            generation: 0,
+            fitness: 0.0,
+            settings: vec![],
+            network: Network::new_empty(),
+        };
+
+        let mut layer: Option<PartialLayer> = None;
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("{}:{}: {}", path, line_number + 1, message);
+
+            let Some((key, value)) = line.split_once('=') else {
+                // a row of numbers for the current layer
+                let Some(partial) = layer.as_mut() else {
+                    return Err(error(format!("unexpected line '{}'", line)));
+                };
+                let values: Vec<f64> = line
+                    .split(',')
+                    .map(|v| v.trim().parse::<f64>().map_err(|e| error(e.to_string())))
+                    .collect::<Result<_, _>>()?;
+
+                if partial.weights.len() < partial.outputs {
+                    if values.len() != partial.inputs {
+                        return Err(error(format!("expected {} weights", partial.inputs)));
+                    }
+                    partial.weights.push(values);
+                } else if partial.recurrent && partial.recurrent_weights.len() < partial.outputs {
+                    if values.len() != partial.outputs {
+                        return Err(error(format!(
+                            "expected {} memory weights",
+                            partial.outputs
+                        )));
+                    }
+                    partial.recurrent_weights.push(values);
+                } else {
+                    if values.len() != partial.outputs {
+                        return Err(error(format!("expected {} biases", partial.outputs)));
+                    }
+                    let weights = std::mem::take(&mut partial.weights);
+                    let mut new_layer = Layer::new(weights, values, partial.activation);
+                    if partial.recurrent {
+                        let recurrent_weights = std::mem::take(&mut partial.recurrent_weights);
+                        new_layer = new_layer.with_recurrent_weights(recurrent_weights);
+                    }
+                    brain.network.layers.push(new_layer);
+                    layer = None;
+                }
+                continue;
+            };
+
+            let (key, value) = (key.trim(), value.trim());
+            if let Some(setting) = key.strip_prefix("setting ") {
+                brain
+                    .settings
+                    .push((setting.trim().to_string(), value.to_string()));
+                continue;
+            }
+
+            let parse_error = |e: std::num::ParseIntError| error(e.to_string());
+            match key {
+                "track" => brain.track_name = value.to_string(),
+                "generation" => brain.generation = value.parse().map_err(parse_error)?,
+                "fitness" => {
+                    brain.fitness = value
+                        .parse()
+                        .map_err(|e: std::num::ParseFloatError| error(e.to_string()))?
+                }
+                "layer" => {
+                    if layer.is_some() {
+                        return Err(error("previous layer isn't finished".to_string()));
+                    }
+                    let parts: Vec<&str> = value.split(',').map(|v| v.trim()).collect();
+                    let recurrent = parts.len() == 4 && parts[3] == "recurrent";
+                    if parts.len() != 3 && !recurrent {
+                        return Err(error(
+                            "expected 'layer = inputs, outputs, activation[, recurrent]'"
+                                .to_string(),
+                        ));
+                    }
+                    layer = Some(PartialLayer {
+                        inputs: parts[0].parse().map_err(parse_error)?,
+                        outputs: parts[1].parse().map_err(parse_error)?,
+                        activation: Activation::from_name(parts[2]).map_err(error)?,
+                        recurrent,
+                        weights: vec![],
+                        recurrent_weights: vec![],
+                    });
+                }
+                _ => return Err(error(format!("unknown key '{}'", key))),
+            }
+        }
+
+        if layer.is_some() {
+            return Err(format!("{}: the last layer isn't finished", path));
+        }
+        if brain.network.layers.is_empty() {
+            return Err(format!("{}: no layers", path));
+        }
+        brain
+            .check_shape()
+            .map_err(|e| format!("{}: {}", path, e))?;
+        return Ok(brain);
+    }
+
+    fn check_shape(&self) -> Result<(), String> {
+        // the layers have to fit together, take what the sensors give and drive the car
+        let shapes = self.network.layer_shapes();
+        for i in 1..shapes.len() {
+            if shapes[i - 1].outputs != shapes[i].inputs {
+                return Err(format!(
+                    "layer {} has {} outputs but layer {} takes {} inputs",
+                    i,
+                    shapes[i - 1].outputs,
+                    i + 1,
+                    shapes[i].inputs
+                ));
+            }
+        }
+
+        let outputs = shapes[shapes.len() - 1].outputs;
+        if outputs != NETWORK_OUTPUTS {
+            return Err(format!(
+                "the last layer has {} outputs, a car needs {}",
+                outputs, NETWORK_OUTPUTS
+            ));
+        }
+
+        let sensor_inputs = self.sensors().input_count();
+        if shapes[0].inputs != sensor_inputs {
+            return Err(format!(
+                "the first layer takes {} inputs but its sensors give {}",
+                shapes[0].inputs, sensor_inputs
+            ));
+        }
+        return Ok(());
+    }
+}
+
+fn join(values: &[f64]) -> String {
+    return values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(", ");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brain_with(layers: Vec<Layer>) -> SavedBrain {
+        let mut network = Network::new_empty();
+        for layer in layers {
+            network = network.add_layer(layer);
+        }
+        return SavedBrain {
+            name: "test".to_string(),
+            track_name: "test_track1".to_string(),
+            generation: 3,
+            fitness: 1234.5,
+            settings: vec![],
+            network,
+        };
+    }
+
+    fn round_trip(brain: &SavedBrain, file_name: &str) -> Result<SavedBrain, String> {
+        let path = std::env::temp_dir().join(file_name);
+        let path = path.to_string_lossy().to_string();
+        brain.save_to(&path).unwrap();
+        let loaded = SavedBrain::load(&path);
+        fs::remove_file(&path).unwrap();
+        return loaded;
+    }
+
+    #[test]
+    fn load_rejects_networks_that_cant_drive() {
+        let inputs = RunConfig::default().sensors.input_count();
+        let sigmoid = Some(Activation::Sigmoid);
+
+        let good = brain_with(vec![
+            Layer::new_random(inputs, 12, None),
+            Layer::new_random(12, NETWORK_OUTPUTS, sigmoid),
+        ]);
+        assert!(round_trip(&good, "racers_good.brain").is_ok());
+
+        let wrong_outputs = brain_with(vec![Layer::new_random(inputs, 2, sigmoid)]);
+        let error = round_trip(&wrong_outputs, "racers_outputs.brain")
+            .err()
+            .unwrap();
+        assert!(error.contains("last layer"), "{}", error);
+
+        let gap = brain_with(vec![
+            Layer::new_random(inputs, 12, None),
+            Layer::new_random(10, NETWORK_OUTPUTS, sigmoid),
+        ]);
+        let error = round_trip(&gap, "racers_gap.brain").err().unwrap();
+        assert!(error.contains("layer 2 takes 10"), "{}", error);
+
+        let wrong_inputs = brain_with(vec![Layer::new_random(
+            inputs + 1,
+            NETWORK_OUTPUTS,
+            sigmoid,
+        )]);
+        let error = round_trip(&wrong_inputs, "racers_inputs.brain")
+            .err()
+            .unwrap();
+        assert!(error.contains("sensors"), "{}", error);
+    }
+
+    #[test]
+    fn recurrent_layer_round_trip() {
+        let inputs = RunConfig::default().sensors.input_count();
+        let brain = brain_with(vec![
+            Layer::new_recurrent(inputs, 6, Some(Activation::Tanh)),
+            Layer::new_random(6, NETWORK_OUTPUTS, Some(Activation::Sigmoid)),
+        ]);
+        let loaded = round_trip(&brain, "racers_recurrent.brain").unwrap();
+
+        assert_eq!(loaded.track_name, brain.track_name);
+        assert_eq!(loaded.generation, brain.generation);
+        assert_eq!(loaded.fitness, brain.fitness);
+        assert_eq!(loaded.network.layer_shapes(), brain.network.layer_shapes());
+        assert!(loaded.network.layers[0].recurrent_weights.is_some());
+        assert!(loaded.network.layers[1].recurrent_weights.is_none());
+        assert_eq!(loaded.network.flatten(), brain.network.flatten());
+        assert_eq!(loaded.network.layers[0].activation, Some(Activation::Tanh));
+    }
+}
@@ -1,32 +1,49 @@
 use crate::network::*;
-use crate::utils::{find_line_eq, lerp, line_intersection, to_rad};
+use crate::utils::{gaussian, lerp, polygon_penetration, ray_segment_intersection};
 use core::f32;
 use macroquad::prelude::*;
 use macroquad::texture::Texture2D;
 use std::f32::consts::PI;
 
-use crate::track::Track;
+use crate::config::RunConfig;
+use crate::controller::*;
+use crate::fitness::{FitnessConfig, FitnessTerms};
+use crate::objectives::{Objectives, WORST_SMOOTHNESS};
+use crate::sensors::SensorConfig;
+use crate::track::{OffTrackMode, OffTrackRule, Surface, Track, TrackPosition};
+use crate::vehicle::{Bicycle, VehicleModel};
 use crate::{WINDOW_HEIGHT, WINDOW_WIDTH};
 
 // consts
 const FRIC_COEF: f32 = 0.88;
 const LAT_FRIC_COEF: f32 = 0.1;
 
-// fitness constannt
-const SECTOR_BONUS: i32 = 1000;
-const LAP_BONUS: i32 = 5000;
-const AVERAGE_SPEED_FACTOR: i32 = 5;
-const SECTOR_SPEED_MULTIPLIER: i32 = 50000;
-const BACK_SECTOR_PUNISHMENT: i32 = -1000;
-const BACK_LAP_PUNISHMENT: i32 = -5000;
-const CRASH_PUNISHMENT: i32 = -10000;
-
-// ai settings
-const NUM_RAYS: usize = 15;
-const AI_FOV: f32 = 200.0;
+// friction on the other surfaces (rolling, lateral)
+// higher rolling friction slows the car more, lower lateral friction means less grip
+const KERB_FRICTION: (f32, f32) = (1.1, 0.08);
+const GRAVEL_FRICTION: (f32, f32) = (3.5, 0.05);
+const GRASS_FRICTION: (f32, f32) = (2.0, 0.03);
 
 // colours
 const TRANSPARENT_COLOUR: Color = color_u8!(255, 255, 255, 50);
+const HUMAN_COLOUR: Color = color_u8!(120, 190, 255, 255);
+
+// the human driven car gets its own number so it shows up on the lap times
+pub const HUMAN_CAR_NUMBER: usize = usize::MAX;
+// same for the classical baseline driver
+pub const BASELINE_CAR_NUMBER: usize = usize::MAX - 1;
+
+// a fast car can skip over a short sector in one tick, jumps up to this many still count
+const MAX_SECTOR_SKIP: usize = 3;
+
+// slower than this (px/s) counts as parked, steering while parked isn't driving
+const MOVING_SPEED: f32 = 10.0;
+
+// how bouncy car to car collisions are, 0 is no bounce and 1 is perfectly elastic
+const CAR_RESTITUTION: f32 = 0.3;
+
+// the number and corners of another car, for collisions and ray sensors
+pub type CarOutline = (usize, [Vec2; 4]);
 
 #[derive(Clone)]
 pub struct Car {
@@ -39,42 +56,68 @@ pub struct Car {
     // -- Scalar
     angle: f32,
     steer: f32,
+    bicycle: Bicycle, // only used with the bicycle vehicle model
 
     // Graphics
     texture: Texture2D,
     rect: Rect,
 
-    // network
-    pub brain: Network,
+    // whatever is driving the car and what it can sense
+    controller: Box<dyn Controller>,
+    sensors: SensorConfig,
+    colour: Color,
+
+    // every control input along with the frame time, for replays
+    recording: Vec<(f32, Controls)>,
+    start_state: (Vec2, f32), // centre and angle the car started with
+
+    // centre and angle every tick of the current lap and the last finished one, for ghosts
+    lap_trajectory: Vec<(Vec2, f32)>,
+    last_lap: Vec<(Vec2, f32)>,
 
     // inputs for controllers
-    accelerator_input: Input,
-    steering_input: Input, // radians
-    brakes_input: Input,
+    inputs: ControlInputs,
 
     // others
     pub crashed: bool,
-    pub fitness: i32,
+    terms: FitnessTerms, // what the fitness is made from, added up as it drives
     pub number: usize,
     pub just_lapped: bool,
+    pub collidable: bool, // clear of every car it started on top of, so other cars' rays see it
+    passing_through: Option<Vec<usize>>, // numbers of the cars it started on top of, None until checked
 
     // stats
     cumulative_speed: f32,
     timer: i32, // sector timer
     prev_checkpoint: usize,
+    track_position: TrackPosition, // where the car is along the centreline
+    progress: f32, // distance driven along the centreline from its first point, negative if reversing
     pub laps: usize,
     pub lap_time: usize, // keeps track of current lap time
     lap_timer: usize,    // times the lap rather than sector
+    best_lap: Option<usize>,
+    ticks_driven: u32,    // ticks before it crashed
+    steering_change: f32, // total movement of the steering while moving, in full locks
+    ticks_moving: u32,
+    last_steering: f32,
 }
 
 #[derive(Default, Clone, Copy)]
 pub struct Input {
     min: f32,
-    weight: f32,
+    pub weight: f32,
     max: f32,
     default: f32,
 }
 
+// the three inputs a controller drives, every control goes through here
+#[derive(Clone, Copy)]
+pub struct ControlInputs {
+    pub accelerator: Input,
+    pub steering: Input, // radians
+    pub brakes: Input,
+}
+
 impl Car {
     pub const HITBOX_WIDTH: f32 = 30.0;
     pub const HITBOX_HEIGHT: f32 = 60.0;
@@ -85,17 +128,44 @@ impl Car {
     pub const MASS: f32 = 40.0;
     pub const BRAKING_FACTOR: f32 = 0.9;
 
-    pub fn new(start_pos: Vec2, number: usize) -> Self {
-        // default car setup
+    pub fn new(start_pos: Vec2, number: usize, config: &RunConfig) -> Self {
+        // default car setup, the first layer takes whatever the sensors give
+        // and can remember what it saw last tick
+        let inputs = config.sensors.input_count();
+        let mut first_layer = Layer::new_random(inputs, 12, None);
+        if config.recurrent_layer {
+            first_layer = Layer::new_recurrent(inputs, 12, Some(Activation::Tanh));
+        }
+
         let mut brain = Network::new_empty();
         brain = brain
-            .add_layer(Layer::new_random(6 + NUM_RAYS, 12, None))
+            .add_layer(first_layer)
             .add_layer(Layer::new_random(12, 8, None))
             .add_layer(Layer::new_random(8, 5, None))
-            .add_layer(Layer::new_random(5, 3, Some(sigmoid)));
+            .add_layer(Layer::new_random(
+                5,
+                NETWORK_OUTPUTS,
+                Some(Activation::Sigmoid),
+            ));
 
+        return Self::with_brain(start_pos, number, brain, config);
+    }
+
+    pub fn with_brain(start_pos: Vec2, number: usize, brain: Network, config: &RunConfig) -> Self {
+        // the sensors and latency for the run
+        let controller = NetworkController::new(brain).with_latency(config.actuator_latency);
+        let car = Self::with_controller(start_pos, number, Box::new(controller), WHITE);
+        return car.with_sensors(config.sensors);
+    }
+
+    pub fn with_controller(
+        start_pos: Vec2,
+        number: usize,
+        controller: Box<dyn Controller>,
+        colour: Color,
+    ) -> Self {
         let mut car: Self = Self {
-            texture: Texture2D::from_file_with_format(include_bytes!("../assets/car.png"), None),
+            texture: Self::load_texture(),
 
             // Defining Vector
             position: vec2(
@@ -109,46 +179,83 @@ impl Car {
             // Scalar
             angle: 0.0,
             steer: 0.0,
+            bicycle: Bicycle::default(),
 
             // other
             rect: Rect::new(0.0, 0.0, Car::HITBOX_WIDTH, Car::HITBOX_HEIGHT),
 
-            // brain
-            brain,
+            // driver
+            controller,
+            sensors: SensorConfig::default(),
+            colour,
+
+            recording: vec![],
+            start_state: (start_pos, 0.0),
+            lap_trajectory: vec![],
+            last_lap: vec![],
 
             // inputs
-            accelerator_input: Input::new_default(),
-            brakes_input: Input::new_default(),
-            steering_input: Input {
-                min: -1.0,
-                max: 1.0,
-                weight: 0.0,
-                default: 0.0,
-            },
+            inputs: ControlInputs::new(),
 
             crashed: false,
-            fitness: 0,
+            terms: FitnessTerms::default(),
             number,
             just_lapped: false,
+            collidable: false,
+            passing_through: None,
 
             // stats
             cumulative_speed: 0.0,
             timer: 0,
             prev_checkpoint: 0,
+            track_position: TrackPosition::default(),
+            progress: 0.0,
             laps: 0,
             lap_timer: 0,
             lap_time: 0,
+            best_lap: None,
+            ticks_driven: 0,
+            steering_change: 0.0,
+            ticks_moving: 0,
+            last_steering: 0.0,
         };
         car.direction = Vec2::from_angle(car.angle);
         return car;
     }
 
+    pub fn new_human(start_pos: Vec2) -> Self {
+        let controller = Box::new(KeyboardController);
+        return Self::with_controller(start_pos, HUMAN_CAR_NUMBER, controller, HUMAN_COLOUR);
+    }
+
+    pub fn with_sensors(mut self, sensors: SensorConfig) -> Self {
+        self.sensors = sensors;
+        return self;
+    }
+
+    pub fn load_texture() -> Texture2D {
+        // tests run without a window to load it into, so they get a texture that's never drawn
+        if cfg!(test) {
+            let id = miniquad::TextureId::from_raw_id(miniquad::RawId::OpenGl(0));
+            return Texture2D::from_miniquad_texture(id);
+        }
+        return Texture2D::from_file_with_format(include_bytes!("../assets/car.png"), None);
+    }
+
+    pub fn brain(&self) -> Option<&Network> {
+        return self.controller.network();
+    }
+
+    pub fn driver_name(&self) -> &str {
+        return self.controller.name();
+    }
+
     pub fn draw(&self, best: bool) {
-        let mut draw_colour = WHITE;
-        if (self.crashed) {
+        let mut draw_colour = self.colour;
+        if self.crashed {
             draw_colour = TRANSPARENT_COLOUR;
         }
-        if (best) {
+        if best {
             draw_colour = color_u8!(255, 215, 0, 255); // goldish colour
         }
 
@@ -171,69 +278,95 @@ impl Car {
     fn toll_fitness(&mut self, track: &Track) {
         if !self.crashed {
             // increase fitness while not crashed
-            self.fitness += 1;
+            self.terms.alive += 1.0;
         } else {
             return;
         }
 
         self.cumulative_speed += self.velocity.length();
-        let sector = self.get_sector(track);
-        let last_sector = track.get_points().len() - 1;
-
-        // if reached a NEXT checkpoint
-        if sector == (self.prev_checkpoint as i32 + 1) {
-            self.prev_checkpoint += 1;
-            let sector_time: i32 = self.timer;
-
-            let speed_bonus =
-                (SECTOR_SPEED_MULTIPLIER as f32 * (1.0 / (sector_time as f32).powf(2.0))) as i32;
-
-            self.fitness += SECTOR_BONUS + speed_bonus;
-            self.timer = 0;
-        } else if (sector == 0 && self.prev_checkpoint == last_sector) {
-            // done a lap
-            let sector_time = self.timer;
-
-            let speed_bonus =
-                (SECTOR_SPEED_MULTIPLIER as f32 * (1.0 / (sector_time as f32).powf(2.0))) as i32;
-
-            self.fitness += speed_bonus + LAP_BONUS;
-
-            self.prev_checkpoint = 0;
+        let sector = self.get_sector();
+        let sector_count = track.sector_count();
+
+        // how many sectors forward it has gone, on tiny tracks a big jump could be backwards
+        let ahead = (sector as usize + sector_count - self.prev_checkpoint) % sector_count;
+        let max_skip = MAX_SECTOR_SKIP.min(sector_count / 2).max(1);
+
+        // if reached a NEXT checkpoint, or skipped a few
+        if ahead >= 1 && ahead <= max_skip {
+            // the sectors crossed share the time between them
+            let sector_time = (self.timer.max(1) as f64 / ahead as f64).max(1.0);
+            for _ in 0..ahead {
+                self.terms.sector_speed += 1.0 / sector_time.powf(2.0);
+                self.prev_checkpoint = (self.prev_checkpoint + 1) % sector_count;
+                if self.prev_checkpoint != 0 {
+                    self.terms.sectors += 1.0;
+                    continue;
+                }
 
+                // done a lap
+                self.terms.laps += 1.0;
+                self.lap_time = self.lap_timer;
+                self.lap_timer = 0;
+                self.best_lap = Some(
+                    self.best_lap
+                        .map_or(self.lap_time, |b| b.min(self.lap_time)),
+                );
+                self.last_lap = std::mem::take(&mut self.lap_trajectory);
+                self.just_lapped = true;
+                self.laps += 1;
+            }
             self.timer = 0;
-            self.lap_time = self.lap_timer;
-            self.lap_timer = 0;
-            self.just_lapped = true;
-            self.laps += 1;
-        } else {
-            if self.prev_checkpoint == 0 && sector == last_sector as i32 {
+        } else if ahead != 0 {
+            if sector as usize > self.prev_checkpoint {
                 // gone backwards past the finish line
                 // aka the car crashed
                 self.crashed = true;
                 self.timer = 0;
-                self.prev_checkpoint = last_sector;
-                self.fitness += BACK_LAP_PUNISHMENT; // DONT GO BACKWARDS
-            } else if sector < self.prev_checkpoint as i32 {
+                self.prev_checkpoint = sector as usize;
+                self.terms.back_laps += 1.0; // DONT GO BACKWARDS
+            } else {
                 // going backwards
                 self.timer = 0;
                 self.prev_checkpoint = sector as usize;
-                self.fitness += BACK_SECTOR_PUNISHMENT;
+                self.terms.back_sectors += 1.0;
             }
         }
     }
 
-    pub fn get_final_fitness(&self, ticks: u32) -> i32 {
-        let mut fitness = self.fitness;
+    pub fn get_fitness_terms(&self, track: &Track, ticks: u32) -> FitnessTerms {
+        // the terms added up while driving and the ones that need the whole run
+        let mut terms = self.terms;
+        terms.crashed = self.crashed as i32 as f64;
+        terms.average_speed = (self.cumulative_speed / ticks as f32) as f64;
+        terms.distance = self.get_progress(track) as f64;
+        terms.lap_fraction = terms.distance / track.get_total_length() as f64;
+        terms.best_lap = self.best_lap;
+        return terms;
+    }
+
+    pub fn get_final_fitness(&self, track: &Track, ticks: u32, fitness: &FitnessConfig) -> f64 {
+        return fitness.score(&self.get_fitness_terms(track, ticks));
+    }
 
+    pub fn get_objectives(&self, track: &Track, time_limit: u32) -> Objectives {
+        // the same run as the final fitness without squashing it into one number
+        // measured against the time limit so every generation is scored the same way
+        let mut survival = 1.0;
         if self.crashed {
-            fitness += CRASH_PUNISHMENT;
+            survival = self.ticks_driven as f64 / time_limit as f64;
         }
 
-        let avg_speed = self.cumulative_speed as i32 / ticks as i32;
-        fitness += avg_speed * AVERAGE_SPEED_FACTOR;
-
-        return fitness;
+        // a car that never got going is as jerky as it gets, or parking would be the smoothest drive
+        let mut smoothness = WORST_SMOOTHNESS;
+        if self.ticks_moving > 0 {
+            smoothness = self.steering_change as f64 / self.ticks_moving as f64;
+        }
+        return Objectives {
+            progress: self.get_progress(track) as f64,
+            lap_time: self.best_lap.map_or(time_limit as f64, |lap| lap as f64),
+            smoothness,
+            survival,
+        };
     }
 
     pub fn update_pos(&mut self, x: f32, y: f32) {
@@ -246,217 +379,330 @@ impl Car {
         self.rect.y = y;
     }
 
-    pub fn update(&mut self, track: &Track, dt: f32) {
+    pub fn step(&mut self, track: &Track, dt: f32, config: &RunConfig, traffic: &[CarOutline]) {
+        // move the car then check if it has crashed
+        self.update(track, dt, config, traffic);
+        if config.off_track_mode == OffTrackMode::Crash
+            && !self.is_on_track(track, config.off_track_rule)
+        {
+            self.crashed();
+        }
+        // obstacles always crash the car, whatever the off track mode
+        if self.hit_obstacle(track) {
+            self.crashed();
+        }
+    }
+
+    pub fn update(&mut self, track: &Track, dt: f32, config: &RunConfig, traffic: &[CarOutline]) {
         self.just_lapped = false;
         self.toll_fitness(track);
 
-        if self.crashed == true {
+        if self.crashed {
             return;
         }
 
-        // run the neural network with inputs
-        let rays = self.cast_rays(NUM_RAYS, AI_FOV, track);
-        let velx_norm = self.velocity.x / Car::MAX_SPEED;
-        let vely_norm = self.velocity.y / Car::MAX_SPEED;
-        let accx_norm = self.acceleration.x / Car::MAX_ACC;
-        let accy_norm = self.acceleration.y / Car::MAX_ACC;
-        let steer_norm = self.steer / Car::STEER_WEIGHT;
-        let angle_norm = (self.angle).sin();
-
-        let mut inputs: Vec<f64> = vec![];
-        for ray in rays.iter() {
-            inputs.push((*ray) as f64);
-        }
-        inputs.push(velx_norm as f64);
-        inputs.push(vely_norm as f64);
-        inputs.push(accx_norm as f64);
-        inputs.push(accy_norm as f64);
-        inputs.push(steer_norm as f64);
-        inputs.push(angle_norm as f64);
-
-        // run the network
-        let outputs = self.brain.run(inputs);
-        self.accelerator_input.weight = outputs[0] as f32;
-        self.steering_input.weight = ((outputs[1] - 0.5) * 2.0) as f32; // convert to value between -1.0 and 1.0
-        self.brakes_input.weight = outputs[2] as f32;
-
-        self.steer = self.steering_input.weight * Car::STEER_WEIGHT;
+        // ask the controller what to do
+        let mut observation = self.observe(track, dt, traffic);
+        self.add_sensor_noise(&mut observation, config);
+        let controls = self.controller.control(&observation);
+        self.recording.push((dt, controls));
+
+        // every controller goes through the same limits
+        self.inputs.apply(controls);
+        let steering = self.inputs.steering.weight;
+        if self.velocity.length() > MOVING_SPEED {
+            self.steering_change += (steering - self.last_steering).abs() / Car::MAX_TURNING_ANGLE;
+            self.ticks_moving += 1;
+        }
+        self.last_steering = steering;
+
+        self.steer = self.inputs.arcade_steer();
+        let friction = self.get_friction(track, config);
+
+        match config.vehicle_model {
+            VehicleModel::Arcade => self.move_arcade(dt, friction),
+            VehicleModel::Bicycle => {
+                let (fric_coef, lat_fric_coef) = friction;
+                let grip = lat_fric_coef / LAT_FRIC_COEF;
+                self.bicycle.step(
+                    &mut self.velocity,
+                    &mut self.angle,
+                    self.inputs.controls(),
+                    fric_coef,
+                    grip,
+                    dt,
+                );
+                self.direction = Vec2::from_angle(self.angle);
+                self.acceleration =
+                    self.direction * (self.inputs.accelerator.weight * Car::MAX_ACC);
+            }
+        }
+
+        self.velocity = cap_speed(self.velocity);
+        self.position += self.velocity * dt;
+        self.update_pos(self.position.x, self.position.y);
+        self.update_track_position(track);
+        self.lap_trajectory.push((self.rect.center(), self.angle));
+
+        // reset inputs
+        self.inputs.reset();
+
+        // increment time
+        self.timer += 1;
+        self.lap_timer += 1;
+        self.ticks_driven += 1;
+    }
+
+    fn move_arcade(&mut self, dt: f32, friction: (f32, f32)) {
+        // the original model, turns at the same rate whatever the speed
         let new_angle = self.angle + self.steer;
 
         self.angle = lerp(self.angle, new_angle, dt * 6.0);
         self.direction = Vec2::from_angle(self.angle);
 
-        self.acceleration = self.direction * (self.accelerator_input.weight * Car::MAX_ACC);
+        self.acceleration = self.direction * (self.inputs.accelerator.weight * Car::MAX_ACC);
         self.velocity += self.acceleration * dt;
 
-        let brake_friction = -self.velocity * self.brakes_input.weight * Car::BRAKING_FACTOR;
+        let brake_friction = -self.velocity * self.inputs.brakes.weight * Car::BRAKING_FACTOR;
         self.velocity += brake_friction * dt;
 
-        let normal_fric: Vec2 = -self.velocity * FRIC_COEF;
+        let (fric_coef, lat_fric_coef) = friction;
+        let normal_fric: Vec2 = -self.velocity * fric_coef;
 
         let perp_direction = self.direction.perp();
         let lateral_velocity =
             self.velocity.dot(perp_direction.normalize()) * perp_direction.normalize();
-        let lateral_fric = -lateral_velocity * LAT_FRIC_COEF;
+        let lateral_fric = -lateral_velocity * lat_fric_coef;
 
         // apply frictions
         self.velocity += (normal_fric) * dt;
-        self.velocity += (lateral_fric); // apply lateral friction
-        self.position += self.velocity * dt;
-        self.update_pos(self.position.x, self.position.y);
+        self.velocity += lateral_fric; // apply lateral friction
+    }
 
-        // reset inputs
-        self.brakes_input.weight = 0.0;
-        self.accelerator_input.weight = 0.0;
-        self.steering_input.weight = 0.0;
+    fn get_friction(&self, track: &Track, config: &RunConfig) -> (f32, f32) {
+        // when leaving the track crashes the car it's always on tarmac
+        if config.off_track_mode == OffTrackMode::Crash {
+            return (FRIC_COEF, LAT_FRIC_COEF);
+        }
 
-        // increment time
-        self.timer += 1;
-        self.lap_timer += 1;
+        // average the friction under each of the four wheels
+        let segment = Some(self.get_segment());
+        let mut total = (0.0, 0.0);
+        for corner in self.get_corners() {
+            let (fric, lat_fric) = match track.surface_at(corner, segment) {
+                Surface::Tarmac => (FRIC_COEF, LAT_FRIC_COEF),
+                Surface::Kerb => KERB_FRICTION,
+                Surface::Gravel => GRAVEL_FRICTION,
+                Surface::Grass => GRASS_FRICTION,
+            };
+            total.0 += fric / 4.0;
+            total.1 += lat_fric / 4.0;
+        }
+        return total;
+    }
+
+    fn update_track_position(&mut self, track: &Track) {
+        // project the car onto the centreline, searching near the last sector
+        let previous = self.track_position;
+        self.track_position = track.project(self.rect.center(), Some(previous.segment));
+        self.progress +=
+            track.distance_between(previous.distance_along, self.track_position.distance_along);
+    }
+
+    pub fn get_sector(&self) -> i32 {
+        return self.track_position.sector as i32;
     }
 
-    pub fn get_sector(&self, track: &Track) -> i32 {
-        let mut closest_sector = 0;
-        let mut shortest_distance: f32 = f32::MAX;
+    pub fn get_segment(&self) -> usize {
+        // the centreline segment, finer than the sector once smoothed
+        return self.track_position.segment;
+    }
 
-        for i in 0..track.get_points().len() {
-            // calculate the midpoint
-            let p1 = track.get_points()[i];
-            let p2 = track.get_points()[(i + 1) % track.get_points().len()];
+    pub fn get_recording(&self) -> &[(f32, Controls)] {
+        return &self.recording;
+    }
 
-            let mp = (p1 + p2) / 2.0;
+    pub fn get_last_lap(&self) -> &[(Vec2, f32)] {
+        return &self.last_lap;
+    }
 
-            let center = self.rect.center();
-            let distance = mp.distance(center);
+    pub fn get_start_state(&self) -> (Vec2, f32) {
+        return self.start_state;
+    }
 
-            if distance < shortest_distance {
-                shortest_distance = distance;
-                closest_sector = i as i32;
-            }
+    pub fn set_angle(&mut self, angle: f32) {
+        self.angle = angle;
+        self.direction = Vec2::from_angle(angle);
+        self.start_state.1 = angle;
+    }
+
+    pub fn get_lap_timer(&self) -> usize {
+        // ticks since the start of the current lap
+        return self.lap_timer;
+    }
+
+    pub fn get_progress(&self, track: &Track) -> f32 {
+        // continuous distance driven along the track since the start line
+        // going backwards takes it away, so reversing over the line is negative
+        return self.progress - track.get_start_distance();
+    }
+
+    fn observe<'a>(&self, track: &'a Track, dt: f32, traffic: &[CarOutline]) -> Observation<'a> {
+        // the sensor inputs for the network, rays first then the scalars
+        let sensors = self.sensors;
+        let rays = self.cast_rays(sensors.rays, sensors.fov, 0.0, track, traffic);
+        let rear_rays =
+            self.cast_rays(sensors.rear_rays, sensors.rear_fov(), 180.0, track, traffic);
+
+        let mut inputs: Vec<f32> = vec![];
+        inputs.extend(rays.iter());
+        inputs.extend(rear_rays.iter());
+        if sensors.speed {
+            inputs.push(self.velocity.length() / Car::MAX_SPEED);
+        }
+        if sensors.velocity {
+            inputs.push(self.velocity.x / Car::MAX_SPEED);
+            inputs.push(self.velocity.y / Car::MAX_SPEED);
+        }
+        if sensors.local_velocity {
+            let forward = Vec2::from_angle(self.angle);
+            inputs.push(self.velocity.dot(forward) / Car::MAX_SPEED);
+            inputs.push(self.velocity.dot(forward.perp()) / Car::MAX_SPEED);
+        }
+        if sensors.acceleration {
+            inputs.push(self.acceleration.x / Car::MAX_ACC);
+            inputs.push(self.acceleration.y / Car::MAX_ACC);
         }
-        return closest_sector;
+        if sensors.steer {
+            inputs.push(self.steer / Car::STEER_WEIGHT);
+        }
+        if sensors.heading {
+            inputs.push(self.angle.sin());
+        }
+
+        // the track ahead in the car's frame, angles go from -1 to 1 for -180 to 180 degrees
+        let forward = Vec2::from_angle(self.angle);
+        let along = self.track_position.distance_along;
+        for i in 1..=sensors.lookahead_points {
+            let ahead = track.direction_at_distance(along + sensors.lookahead_spacing * i as f32);
+            inputs.push(forward.angle_between(ahead) / PI);
+        }
+        if sensors.track_offset {
+            let position = self.track_position;
+            let half_width = track.width_at(position.segment, position.t) / 2.0;
+            inputs.push(position.offset / half_width.max(1.0));
+        }
+        if sensors.heading_error {
+            inputs.push(forward.angle_between(track.direction_at_distance(along)) / PI);
+        }
+        let inputs: Vec<f64> = inputs.iter().map(|input| *input as f64).collect();
+
+        return Observation {
+            sensors: inputs,
+            rays,
+            position: self.rect.center(),
+            velocity: self.velocity,
+            angle: self.angle,
+            track_position: self.track_position,
+            track,
+            dt,
+        };
     }
 
-    fn keyboard_control(&mut self) {
-        // loop through keys
-        for key in get_keys_down() {
-            if (key == KeyCode::Up) {
-                self.accelerator_input.weight = 1.0;
+    fn add_sensor_noise(&self, observation: &mut Observation, config: &RunConfig) {
+        // the rays come first in the network inputs, a dropped ray sees nothing in range
+        let ray_count = self.sensors.rays + self.sensors.rear_rays;
+        for (i, sensor) in observation.sensors.iter_mut().enumerate() {
+            if i < ray_count
+                && config.ray_dropout > 0.0
+                && rand::gen_range(0.0, 1.0) < config.ray_dropout
+            {
+                *sensor = 1.0;
+                continue;
             }
-            if (key == KeyCode::Down) {
-                self.brakes_input.weight = 1.0;
-            }
-            if (key == KeyCode::Left) {
-                self.steering_input.weight = -1.0;
-            }
-            if (key == KeyCode::Right) {
-                self.steering_input.weight = 1.0;
+            if config.sensor_noise > 0.0 {
+                *sensor += gaussian(config.sensor_noise) as f64;
             }
         }
-        // clamping speeds and steering
-        if (self.velocity.length() > Car::MAX_SPEED) {
-            self.velocity = ((self.velocity) / self.velocity.length()) * Car::MAX_SPEED;
+    }
+
+    pub fn get_corners(&self) -> [Vec2; 4] {
+        // corners of the hitbox rotated to face the way the car is pointing
+        // the long side of the hitbox runs along the direction of the car
+        let center = self.rect.center();
+        let forward = Vec2::from_angle(self.angle) * (Car::HITBOX_HEIGHT / 2.0);
+        let side = Vec2::from_angle(self.angle).perp() * (Car::HITBOX_WIDTH / 2.0);
+
+        return [
+            center + forward + side,
+            center + forward - side,
+            center - forward - side,
+            center - forward + side,
+        ];
+    }
+
+    pub fn is_on_track(&self, track: &Track, rule: OffTrackRule) -> bool {
+        let segment = Some(self.get_segment());
+
+        if rule == OffTrackRule::Centre {
+            return track.is_point_on_track(self.rect.center(), segment);
+        }
+
+        // count how many of the wheels (hitbox corners) are off
+        let mut wheels_off = 0;
+        for corner in self.get_corners() {
+            if !track.is_point_on_track(corner, segment) {
+                wheels_off += 1;
+            }
         }
+
+        return !rule.is_off(wheels_off);
     }
 
-    pub fn is_on_track(&self, track: &Track) -> bool {
-        // find the current sector
-        let sector: usize = self.get_sector(track) as usize;
+    pub fn hit_obstacle(&self, track: &Track) -> bool {
+        return track.hits_obstacle(&self.get_corners());
+    }
 
-        // find the sector line equation
-        let points = track.get_points();
-        let p1 = points[sector];
-        let p2 = points[(sector + 1) % points.len()];
-        let coef_vec = find_line_eq(p1.x, p1.y, p2.x, p2.y);
-        let a = coef_vec.x;
-        let b = 1.0;
-        let c = coef_vec.y;
+    pub fn cast_ray(&self, track: &Track, ray_direction: Vec2, traffic: &[CarOutline]) -> f32 {
+        // returns distance to the nearest track edge or other car, up to the sensor range
+        let origin = self.rect.center();
+        let mut nearest = track.cast_ray(origin, ray_direction, self.sensors.range);
 
-        // use the formula for distance
-        let center = self.rect.center();
-        let distance = (a * center.x + b * center.y + c).abs() / (a * a + b * b).sqrt();
-
-        if distance > (track.get_width() / 2.0) {
-            // off the track
-            return false;
-        }
-
-        return true;
-    }
-
-    pub fn cast_ray(&self, track: &Track, ray_direction: Vec2) -> f32 {
-        // returns distance to line sector
-
-        let track_width = track.get_width();
-        let current_sector: usize = self.get_sector(track) as usize;
-        let points = track.get_points();
-
-        let s1 = self.rect.center();
-        let s2 = s1 + ray_direction * WINDOW_WIDTH as f32 * 5.0;
-
-        let mut shortest_interection_point: Vec2 = Vec2::MAX;
-        let mut shortest_distance: f32 = WINDOW_WIDTH as f32;
-
-        for i in 0..points.len() {
-            // finding points A, B, C, D
-            let sector_index = (current_sector + i) % points.len();
-            let sector = points[sector_index];
-            let next_sector = points[(sector_index + 1) % points.len()];
-            let prev_sector = points[(sector_index + (points.len() - 1)) % points.len()];
-            let next_next_sector = points[(sector_index + 2) % points.len()];
-
-            // finding normal for A and C
-            let direction1_left = sector - prev_sector;
-            let direction2_left = next_sector - sector;
-            let normal1_left = vec2(-direction1_left.y, direction1_left.x);
-            let normal2_left = vec2(-direction2_left.y, direction2_left.x);
-            let avg_normal_left = ((normal1_left + normal2_left) / 2.0).normalize();
-
-            // finding normal for B and D
-            let direction1_right = next_sector - sector;
-            let direction2_right = next_next_sector - next_sector;
-            let normal1_right = vec2(-direction1_right.y, direction1_right.x);
-            let normal2_right = vec2(-direction2_right.y, direction2_right.x);
-            let avg_normal_right = ((normal1_right + normal2_right) / 2.0).normalize();
-
-            // calculating points
-            let A = sector + avg_normal_left * (track_width / 2.0);
-            let B = next_sector + avg_normal_right * (track_width / 2.0);
-            let C = sector - avg_normal_left * (track_width / 2.0);
-            let D = next_sector - avg_normal_right * (track_width / 2.0);
-
-            if let Some(point1) = line_intersection(s1, s2, A, B) {
-                let distance = (point1 - s1).length();
-                if distance < shortest_distance {
-                    shortest_interection_point = point1;
-                    shortest_distance = distance;
-                }
-            } else if let Some(point2) = line_intersection(s1, s2, C, D) {
-                let distance = (point2 - s1).length();
-                if distance < shortest_distance {
-                    shortest_interection_point = point2;
-                    shortest_distance = distance;
+        for (number, corners) in traffic.iter() {
+            if *number == self.number {
+                continue;
+            }
+            for i in 0..4 {
+                let (a, b) = (corners[i], corners[(i + 1) % 4]);
+                if let Some(t) = ray_segment_intersection(origin, ray_direction, a, b) {
+                    nearest = nearest.min(t).min(self.sensors.range);
                 }
             }
         }
-
-        shortest_distance
+        return nearest;
     }
 
-    pub fn cast_rays(&self, rays: usize, fov: f32, track: &Track) -> Vec<f32> {
-        // fov in degrees
+    pub fn cast_rays(
+        &self,
+        rays: usize,
+        fov: f32,
+        facing: f32,
+        track: &Track,
+        traffic: &[CarOutline],
+    ) -> Vec<f32> {
+        // fov and facing in degrees, facing is relative to the front of the car
 
         let mut ray_list: Vec<f32> = vec![];
 
-        let start_angle = self.angle.to_degrees() - fov / 2.0;
+        let start_angle = self.angle.to_degrees() + facing - fov / 2.0;
         let step = fov / rays as f32;
 
         for ray in 0..rays {
             let angle = start_angle + step * ray as f32;
             let dir = Vec2::from_angle(angle.to_radians());
-            let distance = self.cast_ray(track, dir);
-            // normalize the distance against the window width
-            let normalized = distance / (WINDOW_WIDTH as f32);
+            let distance = self.cast_ray(track, dir, traffic);
+            // normalize the distance against the sensor range
+            let normalized = distance / self.sensors.range;
             ray_list.push(normalized);
         }
 
@@ -467,28 +713,551 @@ impl Car {
         self.crashed = true;
     }
 
-    pub fn reset(&mut self, position: Vec2) {
-        self.position = position;
+    pub fn place_on_grid(&mut self, track: &Track, centre: Vec2, angle: f32) {
+        // start somewhere other than the start line, facing along the track
+        self.reset(centre);
+        self.set_angle(angle);
+        self.track_position = track.project(centre, None);
+        self.prev_checkpoint = self.track_position.sector;
+        // behind the start line counts as not having got to it yet
+        self.progress = track.get_start_distance()
+            + track.distance_between(
+                track.get_start_distance(),
+                self.track_position.distance_along,
+            );
+    }
+
+    pub fn get_centre(&self) -> Vec2 {
+        return self.rect.center();
+    }
+
+    pub fn get_outline(&self) -> CarOutline {
+        return (self.number, self.get_corners());
+    }
+
+    pub fn overlap(&self, other: &Car) -> Option<(Vec2, f32)> {
+        // direction to push the other car out of this one and how far
+        // cars further apart than their length can't be touching
+        let gap = self.rect.center().distance(other.rect.center());
+        if gap > Car::HITBOX_HEIGHT + Car::HITBOX_WIDTH {
+            return None;
+        }
+        return polygon_penetration(&self.get_corners(), &other.get_corners());
+    }
+
+    pub fn collide(&mut self, other: &mut Car, normal: Vec2, depth: f32) {
+        // push the cars apart so they're just touching
+        let push = normal * (depth / 2.0);
+        self.position -= push;
+        other.position += push;
+        self.update_pos(self.position.x, self.position.y);
+        other.update_pos(other.position.x, other.position.y);
+
+        // equal mass impulse along the normal, only if they're moving together
+        let closing_speed = (other.velocity - self.velocity).dot(normal);
+        if closing_speed < 0.0 {
+            let impulse = -(1.0 + CAR_RESTITUTION) * closing_speed / 2.0;
+            self.velocity -= normal * impulse;
+            other.velocity += normal * impulse;
+        }
+    }
+
+    pub fn reset(&mut self, start_pos: Vec2) {
+        // put the car back on the start line, like a new car
+        self.position = vec2(
+            start_pos.x - Self::HITBOX_WIDTH / 2.0,
+            start_pos.y - Self::HITBOX_HEIGHT / 2.0,
+        );
         self.acceleration = Vec2::ZERO;
         self.velocity = Vec2::ZERO;
         self.angle = 0.0;
         self.steer = 0.0;
+        self.bicycle = Bicycle::default();
         self.direction = Vec2::ZERO;
-        self.accelerator_input.weight = 0.0;
-        self.brakes_input.weight = 0.0;
-        self.steering_input.weight = 0.0;
+        self.inputs.reset();
         self.update_pos(self.position.x, self.position.y);
+        self.track_position = TrackPosition::default();
+        self.progress = 0.0;
         self.crashed = false;
+        self.collidable = false;
+        self.passing_through = None;
+        self.controller.reset();
+
+        // start the timing again
+        self.terms = FitnessTerms::default();
+        self.just_lapped = false;
+        self.cumulative_speed = 0.0;
+        self.timer = 0;
+        self.prev_checkpoint = 0;
+        self.laps = 0;
+        self.lap_timer = 0;
+        self.lap_time = 0;
+        self.best_lap = None;
+        self.ticks_driven = 0;
+        self.steering_change = 0.0;
+        self.ticks_moving = 0;
+        self.last_steering = 0.0;
+
+        self.recording.clear();
+        self.lap_trajectory.clear();
+        self.last_lap.clear();
+        self.start_state = (start_pos, 0.0);
+    }
+}
+
+pub fn resolve_collisions(cars: &mut [&mut Car], respond: bool) {
+    // find every pair of cars touching, bouncing them apart if respond is set
+    // cars that started on top of each other pass through each other until they have
+    // been apart once, every other pair collides straight away
+    let mut touched: Vec<Vec<usize>> = vec![vec![]; cars.len()];
+    let mut spawned = vec![false; cars.len()];
+    for (i, car) in cars.iter_mut().enumerate() {
+        if car.passing_through.is_none() {
+            car.passing_through = Some(vec![]);
+            spawned[i] = true;
+        }
+    }
+
+    for j in 1..cars.len() {
+        let (before, after) = cars.split_at_mut(j);
+        let other = &mut after[0];
+        if other.crashed {
+            continue;
+        }
+        for i in 0..j {
+            let car = &mut before[i];
+            if car.crashed {
+                continue;
+            }
+            let Some((normal, depth)) = car.overlap(other) else {
+                continue;
+            };
+            touched[i].push(other.number);
+            touched[j].push(car.number);
+
+            if spawned[i] || spawned[j] {
+                car.passing_through.as_mut().unwrap().push(other.number);
+                other.passing_through.as_mut().unwrap().push(car.number);
+            }
+            let passing = car
+                .passing_through
+                .as_ref()
+                .unwrap()
+                .contains(&other.number);
+            if respond && !passing {
+                car.collide(other, normal, depth);
+            }
+        }
+    }
+
+    // once a pair have got clear of each other they can hit each other
+    for (car, touched) in cars.iter_mut().zip(touched) {
+        let passing_through = car.passing_through.as_mut().unwrap();
+        passing_through.retain(|number| touched.contains(number));
+        if passing_through.is_empty() && !car.crashed {
+            car.collidable = true;
+        }
     }
 }
 
 impl Input {
+    pub fn new(min: f32, max: f32, default: f32) -> Self {
+        Self {
+            min,
+            weight: default,
+            max,
+            default,
+        }
+    }
+
     pub fn new_default() -> Self {
+        return Self::new(0.0, 1.0, 0.0);
+    }
+
+    pub fn set(&mut self, value: f32) {
+        // nan can't be clamped so it falls back to the default, e.g. no throttle
+        if value.is_nan() {
+            self.weight = self.default;
+            return;
+        }
+        self.weight = clamp(value, self.min, self.max);
+    }
+
+    pub fn reset(&mut self) {
+        self.weight = self.default;
+    }
+}
+
+impl ControlInputs {
+    pub fn new() -> Self {
         Self {
-            min: 0.0,
-            weight: 0.0,
-            max: 1.0,
-            default: 0.0,
+            accelerator: Input::new_default(),
+            steering: Input::new(-Car::MAX_TURNING_ANGLE, Car::MAX_TURNING_ANGLE, 0.0),
+            brakes: Input::new_default(),
+        }
+    }
+
+    pub fn apply(&mut self, controls: Controls) {
+        self.accelerator.set(controls.throttle);
+        self.steering.set(controls.steer * Car::MAX_TURNING_ANGLE);
+        self.brakes.set(controls.brake);
+    }
+
+    pub fn arcade_steer(&self) -> f32 {
+        // the arcade model turns STEER_WEIGHT for a full lock, never past the turning limit
+        let steer = self.steering.weight / Car::MAX_TURNING_ANGLE * Car::STEER_WEIGHT;
+        return clamp(steer, -Car::MAX_TURNING_ANGLE, Car::MAX_TURNING_ANGLE);
+    }
+
+    pub fn controls(&self) -> Controls {
+        // the clamped inputs, with the steering back between -1 and 1
+        return Controls {
+            throttle: self.accelerator.weight,
+            steer: self.steering.weight / Car::MAX_TURNING_ANGLE,
+            brake: self.brakes.weight,
+        };
+    }
+
+    pub fn reset(&mut self) {
+        self.accelerator.reset();
+        self.steering.reset();
+        self.brakes.reset();
+    }
+}
+
+pub fn cap_speed(velocity: Vec2) -> Vec2 {
+    // no car goes faster than MAX_SPEED, whatever is driving it
+    if !velocity.is_finite() {
+        return Vec2::ZERO;
+    }
+    if velocity.length() > Car::MAX_SPEED {
+        // scaled down first so huge values don't overflow the length
+        let direction = (velocity / velocity.abs().max_element()).normalize();
+        return direction * Car::MAX_SPEED;
+    }
+    return velocity;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_in_range(inputs: &ControlInputs) {
+        let turn = Car::MAX_TURNING_ANGLE;
+        assert!((0.0..=1.0).contains(&inputs.accelerator.weight));
+        assert!((0.0..=1.0).contains(&inputs.brakes.weight));
+        assert!((-turn..=turn).contains(&inputs.steering.weight));
+    }
+
+    fn square_track() -> Track {
+        let points = [
+            vec2(100.0, 100.0),
+            vec2(500.0, 100.0),
+            vec2(500.0, 500.0),
+            vec2(100.0, 500.0),
+        ];
+        return Track::new(&points, 50.0);
+    }
+
+    // gives the same controls every tick
+    #[derive(Clone)]
+    struct FixedControls(Controls);
+
+    impl Controller for FixedControls {
+        fn control(&mut self, _observation: &Observation) -> Controls {
+            return self.0;
+        }
+
+        fn name(&self) -> &str {
+            return "test";
+        }
+
+        fn box_clone(&self) -> Box<dyn Controller> {
+            return Box::new(self.clone());
+        }
+    }
+
+    fn extreme_network(scale: f64) -> Network {
+        // no activation on the last layer so the outputs aren't squashed
+        let weights = vec![vec![scale; 4]; 3];
+        let bias = vec![scale, -scale, scale];
+        return Network::new_empty().add_layer(Layer::new(weights, bias, None));
+    }
+
+    #[test]
+    fn extreme_network_outputs_are_clamped() {
+        let track = square_track();
+
+        for scale in [1e30, -1e30, f64::MAX, f64::INFINITY, f64::NAN] {
+            let mut controller = NetworkController::new(extreme_network(scale));
+            let observation = Observation {
+                sensors: vec![1.0, -1.0, 1e10, 0.0],
+                rays: vec![],
+                position: vec2(300.0, 100.0),
+                velocity: Vec2::ZERO,
+                angle: 0.0,
+                track_position: TrackPosition::default(),
+                track: &track,
+                dt: 1.0 / 60.0,
+            };
+            let controls = controller.control(&observation);
+
+            let mut inputs = ControlInputs::new();
+            inputs.apply(controls);
+            assert_in_range(&inputs);
+
+            let applied = inputs.controls();
+            assert!((-1.0..=1.0).contains(&applied.steer));
+        }
+    }
+
+    #[test]
+    fn nan_and_infinite_controls() {
+        let mut inputs = ControlInputs::new();
+        inputs.apply(Controls {
+            throttle: f32::INFINITY,
+            steer: f32::NEG_INFINITY,
+            brake: f32::NAN,
+        });
+        assert_in_range(&inputs);
+        assert_eq!(inputs.accelerator.weight, 1.0);
+        assert_eq!(inputs.steering.weight, -Car::MAX_TURNING_ANGLE);
+        assert_eq!(inputs.brakes.weight, 0.0);
+
+        inputs.reset();
+        assert_eq!(inputs.steering.weight, 0.0);
+    }
+
+    #[test]
+    fn arcade_steering_stays_under_the_turning_limit() {
+        let mut inputs = ControlInputs::new();
+        for steer in [1e9, -1e9, f32::INFINITY, f32::NEG_INFINITY, f32::NAN, 0.3] {
+            inputs.apply(Controls {
+                steer,
+                ..Default::default()
+            });
+            let turn = inputs.arcade_steer();
+            assert!(turn.abs() <= Car::MAX_TURNING_ANGLE, "{}: {}", steer, turn);
+        }
+
+        // full lock either way is STEER_WEIGHT
+        inputs.apply(Controls {
+            steer: 1.0,
+            ..Default::default()
+        });
+        assert!((inputs.arcade_steer() - Car::STEER_WEIGHT).abs() < 1e-6);
+        inputs.apply(Controls {
+            steer: -5.0,
+            ..Default::default()
+        });
+        assert!((inputs.arcade_steer() + Car::STEER_WEIGHT).abs() < 1e-6);
+
+        // a car told to steer far past full lock turns no faster than full lock
+        let track = square_track();
+        let controller = FixedControls(Controls {
+            throttle: 1.0,
+            steer: 1e9,
+            brake: 0.0,
+        });
+        let mut car = Car::with_controller(vec2(300.0, 100.0), 1, Box::new(controller), WHITE);
+        let dt = 1.0 / 60.0;
+        car.step(&track, dt, &RunConfig::default(), &[]);
+        assert!(car.angle > 0.0);
+        assert!(car.angle <= Car::STEER_WEIGHT * dt * 6.0 + 1e-6);
+    }
+
+    #[test]
+    fn speed_is_capped() {
+        let capped = cap_speed(vec2(1e20, -1e20));
+        assert!(capped.length() <= Car::MAX_SPEED + 0.01);
+        assert!(capped.x > 0.0 && capped.y < 0.0);
+
+        let slow = vec2(10.0, 20.0);
+        assert_eq!(cap_speed(slow), slow);
+        assert_eq!(cap_speed(vec2(f32::NAN, 0.0)), Vec2::ZERO);
+    }
+
+    #[test]
+    fn bicycle_model_stays_under_limits() {
+        // full throttle and lock for a long time, through the same limits as the car
+        let mut bicycle = Bicycle::default();
+        let mut velocity = Vec2::ZERO;
+        let mut angle = 0.0;
+        let mut inputs = ControlInputs::new();
+
+        for tick in 0..2000 {
+            let steer = if tick % 200 < 100 { 1e9 } else { -1e9 };
+            inputs.apply(Controls {
+                throttle: 1e9,
+                steer,
+                brake: -1e9,
+            });
+            let controls = inputs.controls();
+            bicycle.step(
+                &mut velocity,
+                &mut angle,
+                controls,
+                FRIC_COEF,
+                1.0,
+                1.0 / 60.0,
+            );
+            velocity = cap_speed(velocity);
+
+            assert!(velocity.length() <= Car::MAX_SPEED + 0.01);
+            assert!(angle.is_finite());
+        }
+    }
+
+    fn parked_car(number: usize, centre: Vec2, velocity: Vec2) -> Car {
+        let controller = FixedControls(Controls::default());
+        let mut car = Car::with_controller(centre, number, Box::new(controller), WHITE);
+        move_to(&mut car, centre);
+        car.velocity = velocity;
+        return car;
+    }
+
+    fn move_to(car: &mut Car, centre: Vec2) {
+        car.update_pos(
+            centre.x - Car::HITBOX_WIDTH / 2.0,
+            centre.y - Car::HITBOX_HEIGHT / 2.0,
+        );
+    }
+
+    #[test]
+    fn head_on_collision_bounces_the_cars_apart() {
+        // both facing along x, so 60px long that way
+        let mut a = parked_car(1, vec2(300.0, 300.0), vec2(100.0, 0.0));
+        let mut b = parked_car(2, vec2(400.0, 300.0), vec2(-100.0, 0.0));
+        resolve_collisions(&mut [&mut a, &mut b], true);
+        assert!(a.collidable && b.collidable);
+
+        // 5px into each other nose to nose
+        move_to(&mut b, vec2(355.0, 300.0));
+        resolve_collisions(&mut [&mut a, &mut b], true);
+        assert!(a.overlap(&b).is_none_or(|(_, depth)| depth < 1e-3));
+        assert!((a.get_centre().x - 297.5).abs() < 1e-3);
+        assert!((b.get_centre().x - 357.5).abs() < 1e-3);
+
+        // equal masses swap the closing speed, less what the restitution loses
+        let bounce = 200.0 * CAR_RESTITUTION / 2.0;
+        assert!(
+            (a.velocity - vec2(-bounce, 0.0)).length() < 1e-3,
+            "{}",
+            a.velocity
+        );
+        assert!(
+            (b.velocity - vec2(bounce, 0.0)).length() < 1e-3,
+            "{}",
+            b.velocity
+        );
+    }
+
+    #[test]
+    fn glancing_collision_only_changes_the_sideways_speed() {
+        let mut a = parked_car(1, vec2(300.0, 300.0), vec2(100.0, 20.0));
+        let mut b = parked_car(2, vec2(400.0, 400.0), Vec2::ZERO);
+        resolve_collisions(&mut [&mut a, &mut b], true);
+
+        // corner to corner, 6px along and only 4px across so they are pushed apart sideways
+        move_to(&mut b, vec2(354.0, 326.0));
+        resolve_collisions(&mut [&mut a, &mut b], true);
+        assert!(a.overlap(&b).is_none_or(|(_, depth)| depth < 1e-3));
+        assert!((a.get_centre() - vec2(300.0, 298.0)).length() < 1e-3);
+        assert!((b.get_centre() - vec2(354.0, 328.0)).length() < 1e-3);
+
+        // the speed along the cars is untouched, momentum is kept across them
+        assert_eq!(a.velocity.x, 100.0);
+        assert_eq!(b.velocity.x, 0.0);
+        assert!(b.velocity.y > 0.0 && a.velocity.y < 20.0);
+        assert!((a.velocity.y + b.velocity.y - 20.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn cars_spawned_together_pass_through_until_apart() {
+        let mut a = parked_car(1, vec2(300.0, 300.0), Vec2::ZERO);
+        let mut b = parked_car(2, vec2(300.0, 300.0), Vec2::ZERO);
+        resolve_collisions(&mut [&mut a, &mut b], true);
+        assert!(!a.collidable && !b.collidable);
+        assert_eq!(a.get_centre(), b.get_centre());
+
+        // still overlapping, still passing through
+        move_to(&mut b, vec2(320.0, 300.0));
+        resolve_collisions(&mut [&mut a, &mut b], true);
+        assert!(!a.collidable && !b.collidable);
+        assert_eq!(b.get_centre(), vec2(320.0, 300.0));
+
+        // clear of each other once and they collide from then on
+        move_to(&mut b, vec2(400.0, 300.0));
+        resolve_collisions(&mut [&mut a, &mut b], true);
+        assert!(a.collidable && b.collidable);
+        move_to(&mut b, vec2(350.0, 300.0));
+        resolve_collisions(&mut [&mut a, &mut b], true);
+        assert!(a.overlap(&b).is_none_or(|(_, depth)| depth < 1e-3));
+
+        // a car that spawned clear collides straight away
+        let mut c = parked_car(3, vec2(500.0, 300.0), Vec2::ZERO);
+        resolve_collisions(&mut [&mut a, &mut b, &mut c], true);
+        assert!(c.collidable);
+        move_to(&mut c, vec2(b.get_centre().x + 50.0, 300.0));
+        resolve_collisions(&mut [&mut a, &mut b, &mut c], true);
+        assert!(b.overlap(&c).is_none_or(|(_, depth)| depth < 1e-3));
+    }
+
+    fn noisy_inputs(config: &RunConfig, value: f64, count: usize) -> Vec<f64> {
+        // the rays come first, the rest stand in for the other sensors
+        let car = parked_car(1, vec2(300.0, 100.0), Vec2::ZERO);
+        let track = square_track();
+        let mut observation = Observation {
+            sensors: vec![value; count],
+            rays: vec![],
+            position: vec2(300.0, 100.0),
+            velocity: Vec2::ZERO,
+            angle: 0.0,
+            track_position: TrackPosition::default(),
+            track: &track,
+            dt: 1.0 / 60.0,
+        };
+        car.add_sensor_noise(&mut observation, config);
+        return observation.sensors;
+    }
+
+    #[test]
+    fn sensor_noise_has_the_set_standard_deviation() {
+        rand::srand(44);
+        let mut config = RunConfig::default();
+        assert_eq!(noisy_inputs(&config, 0.5, 100), vec![0.5; 100]);
+
+        config.sensor_noise = 0.1;
+        let inputs = noisy_inputs(&config, 0.5, 20000);
+        let mean = inputs.iter().sum::<f64>() / inputs.len() as f64;
+        let variance =
+            inputs.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / inputs.len() as f64;
+        assert!((mean - 0.5).abs() < 0.005, "{}", mean);
+        assert!((variance.sqrt() - 0.1).abs() < 0.005, "{}", variance.sqrt());
+    }
+
+    #[test]
+    fn ray_dropout_only_blinds_rays() {
+        rand::srand(45);
+        let mut config = RunConfig {
+            ray_dropout: 0.25,
+            ..Default::default()
+        };
+        let rays = config.sensors.rays + config.sensors.rear_rays;
+        assert!(rays > 0);
+
+        let mut dropped = 0;
+        for _ in 0..4000 {
+            let inputs = noisy_inputs(&config, 0.2, rays + 3);
+            // a dropped ray sees nothing in range, the other sensors are never touched
+            dropped += inputs[..rays].iter().filter(|v| **v == 1.0).count();
+            assert!(inputs[..rays].iter().all(|v| *v == 1.0 || *v == 0.2));
+            assert_eq!(inputs[rays..], [0.2; 3]);
         }
+        let rate = dropped as f64 / (4000 * rays) as f64;
+        assert!((rate - 0.25).abs() < 0.02, "{}", rate);
+
+        config.ray_dropout = 1.0;
+        assert_eq!(noisy_inputs(&config, 0.2, rays)[..], vec![1.0; rays][..]);
     }
 }
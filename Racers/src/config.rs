@@ -0,0 +1,325 @@
+use crate::controller::PursuitGains;
+use crate::fitness::{FitnessConfig, FitnessKind, TERM_NAMES};
+use crate::optimiser::OptimiserKind;
+use crate::sensors::SensorConfig;
+use crate::track::{OffTrackMode, OffTrackRule};
+use crate::vehicle::VehicleModel;
+use std::fs;
+
+pub const CONFIG_FILE: &str = "racers.cfg";
+
+// settings for a run that aren't on the main menu
+// read from racers.cfg (key = value per line) and then from the command line
+// as --key=value, so the command line wins
+#[derive(Clone)]
+pub struct RunConfig {
+    pub seed: u64, // 0 picks one from the clock
+    pub off_track_rule: OffTrackRule,
+    pub off_track_mode: OffTrackMode,
+    pub vehicle_model: VehicleModel,
+    pub car_collisions: bool, // cars bump into each other instead of driving through
+    pub cars_in_rays: bool,   // the ray sensors see the other cars
+    pub sensors: SensorConfig, // for new networks, saved brains keep their own
+    pub recurrent_layer: bool, // new networks remember their last hidden layer outputs
+    pub optimiser: OptimiserKind, // how each generation is made from the last
+    pub es_sigma: f64,        // size of the noise the es tries around its mean
+    pub es_learning_rate: f64,
+    pub cma_sigma: f64,              // starting step size for cma-es
+    pub fitness: FitnessConfig,      // what the cars are scored on
+    pub novelty_search: bool,        // score cars by how differently they drive
+    pub novelty_weight: f64,         // 1 is pure novelty, 0 is pure fitness, in between blends them
+    pub novelty_samples: usize,      // positions through the generation that describe a car's drive
+    pub novelty_neighbours: usize,   // how many of the nearest behaviours novelty is measured from
+    pub novelty_archive_rate: usize, // most novel cars added to the archive each generation
+    pub resume: String,              // saved brain (and its novelty archive) to carry on from
+    pub sensor_noise: f32,           // standard deviation of the noise added to every network input
+    pub ray_dropout: f32,            // chance each tick that a ray sees nothing
+    pub actuator_latency: usize,     // ticks before the network's outputs reach the car
+    pub scripted_drivers: usize,     // hand written drivers racing alongside the ai
+    pub baseline_driver: bool,       // the pure pursuit driver, to compare lap times with
+    pub pursuit_gains: PursuitGains,
+    pub race_laps: usize,
+    pub race_tick_limit: u32, // the race ends here even if not everyone has finished
+    pub race_brains: Vec<String>, // saved brains to enter, all of them if empty
+    pub race_human: bool,     // join the race yourself
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            off_track_rule: OffTrackRule::Centre, // the original check, the wheel rules are opt in
+            off_track_mode: OffTrackMode::Crash,
+            vehicle_model: VehicleModel::Arcade,
+            car_collisions: false,
+            cars_in_rays: false,
+            sensors: SensorConfig::default(),
+            recurrent_layer: false,
+            optimiser: OptimiserKind::Genetic,
+            es_sigma: 0.1,
+            es_learning_rate: 0.05,
+            cma_sigma: 0.3,
+            fitness: FitnessConfig::default(),
+            novelty_search: false,
+            novelty_weight: 1.0,
+            novelty_samples: 5,
+            novelty_neighbours: 15,
+            novelty_archive_rate: 2,
+            resume: String::new(),
+            sensor_noise: 0.0,
+            ray_dropout: 0.0,
+            actuator_latency: 0,
+            scripted_drivers: 0,
+            baseline_driver: false,
+            pursuit_gains: PursuitGains::default(),
+            race_laps: 3,
+            race_tick_limit: 10000,
+            race_brains: vec![],
+            race_human: false,
+        }
+    }
+}
+
+impl RunConfig {
+    pub fn load() -> Self {
+        let mut config = Self::default();
+
+        // the file is optional, defaults are used if it isn't there
+        if let Ok(contents) = fs::read_to_string(CONFIG_FILE) {
+            for line in contents.lines() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                if let Some((key, value)) = line.split_once('=') {
+                    config.set(key.trim(), value.trim());
+                } else {
+                    println!("[{}] - Ignoring line '{}'", CONFIG_FILE, line);
+                }
+            }
+        }
+
+        for arg in std::env::args().skip(1) {
+            if let Some((key, value)) = arg.trim_start_matches("--").split_once('=') {
+                config.set(key, value);
+            } else {
+                println!("[args] - Ignoring argument '{}', use --key=value", arg);
+            }
+        }
+
+        return config;
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        let result = match key {
+            "seed" => parse(value).map(|v| self.seed = v),
+            "off_track_rule" => OffTrackRule::from_name(value).map(|v| self.off_track_rule = v),
+            "off_track_mode" => OffTrackMode::from_name(value).map(|v| self.off_track_mode = v),
+            "vehicle_model" => VehicleModel::from_name(value).map(|v| self.vehicle_model = v),
+            "car_collisions" => parse(value).map(|v| self.car_collisions = v),
+            "cars_in_rays" => parse(value).map(|v| self.cars_in_rays = v),
+            "sensor_rays" => parse_positive(value).map(|v| self.sensors.rays = v),
+            "sensor_fov" => parse(value).map(|v| self.sensors.fov = v),
+            "sensor_range" => parse_positive(value).map(|v| self.sensors.range = v),
+            "sensor_rear_rays" => parse(value).map(|v| self.sensors.rear_rays = v),
+            "sensor_speed" => parse(value).map(|v| self.sensors.speed = v),
+            "sensor_velocity" => parse(value).map(|v| self.sensors.velocity = v),
+            "sensor_local_velocity" => parse(value).map(|v| self.sensors.local_velocity = v),
+            "sensor_acceleration" => parse(value).map(|v| self.sensors.acceleration = v),
+            "sensor_steer" => parse(value).map(|v| self.sensors.steer = v),
+            "sensor_heading" => parse(value).map(|v| self.sensors.heading = v),
+            "sensor_lookahead_points" => parse(value).map(|v| self.sensors.lookahead_points = v),
+            "sensor_lookahead_spacing" => parse(value).map(|v| self.sensors.lookahead_spacing = v),
+            "sensor_track_offset" => parse(value).map(|v| self.sensors.track_offset = v),
+            "sensor_heading_error" => parse(value).map(|v| self.sensors.heading_error = v),
+            "recurrent_layer" => parse(value).map(|v| self.recurrent_layer = v),
+            "optimiser" => OptimiserKind::from_name(value).map(|v| self.optimiser = v),
+            "es_sigma" => parse(value).map(|v| self.es_sigma = v),
+            "es_learning_rate" => parse(value).map(|v| self.es_learning_rate = v),
+            "cma_sigma" => parse(value).map(|v| self.cma_sigma = v),
+            "fitness" => FitnessKind::from_name(value).map(|v| self.fitness.kind = v),
+            _ if key.starts_with("fitness_") => parse(value).and_then(|v| {
+                self.fitness
+                    .set_weight(key.trim_start_matches("fitness_"), v)
+            }),
+            "novelty_search" => parse(value).map(|v| self.novelty_search = v),
+            "novelty_weight" => parse(value).map(|v| self.novelty_weight = v),
+            "novelty_samples" => parse(value).map(|v| self.novelty_samples = v),
+            "novelty_neighbours" => parse(value).map(|v| self.novelty_neighbours = v),
+            "novelty_archive_rate" => parse(value).map(|v| self.novelty_archive_rate = v),
+            "resume" => {
+                self.resume = value.to_string();
+                Ok(())
+            }
+            "sensor_noise" => parse(value).map(|v| self.sensor_noise = v),
+            "ray_dropout" => parse(value).map(|v| self.ray_dropout = v),
+            "actuator_latency" => parse(value).map(|v| self.actuator_latency = v),
+            "scripted_drivers" => parse(value).map(|v| self.scripted_drivers = v),
+            "baseline_driver" => parse(value).map(|v| self.baseline_driver = v),
+            "pursuit_lookahead" => parse(value).map(|v| self.pursuit_gains.lookahead = v),
+            "pursuit_steer_gain" => parse(value).map(|v| self.pursuit_gains.steer_gain = v),
+            "pursuit_max_speed" => parse(value).map(|v| self.pursuit_gains.max_speed = v),
+            "pursuit_lateral_accel" => parse(value).map(|v| self.pursuit_gains.lateral_accel = v),
+            "pursuit_braking_lookahead" => {
+                parse(value).map(|v| self.pursuit_gains.braking_lookahead = v)
+            }
+            "pursuit_kp" => parse(value).map(|v| self.pursuit_gains.kp = v),
+            "pursuit_ki" => parse(value).map(|v| self.pursuit_gains.ki = v),
+            "pursuit_kd" => parse(value).map(|v| self.pursuit_gains.kd = v),
+            "race_laps" => parse(value).map(|v| self.race_laps = v),
+            "race_tick_limit" => parse(value).map(|v| self.race_tick_limit = v),
+            "race_brains" => {
+                self.race_brains = value
+                    .split(',')
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty())
+                    .collect();
+                Ok(())
+            }
+            "race_human" => parse(value).map(|v| self.race_human = v),
+            _ => Err(format!("unknown setting '{}'", key)),
+        };
+
+        if let Err(e) = result {
+            println!("[config] - {} = {}: {}", key, value, e);
+        }
+
+        // nsga-ii never looks at the fitness, which is where the novelty goes
+        if self.novelty_search && self.optimiser == OptimiserKind::Nsga2 {
+            println!(
+                "[config] - novelty_search doesn't work with optimiser = nsga2, turning it off"
+            );
+            self.novelty_search = false;
+        }
+    }
+
+    pub fn describe(&self) -> Vec<(String, String)> {
+        // every setting as (key, value), for printing with the run
+        let mut settings: Vec<(String, String)> = vec![
+            ("seed", self.seed.to_string()),
+            ("off_track_rule", self.off_track_rule.name().to_string()),
+            ("off_track_mode", self.off_track_mode.name().to_string()),
+            ("vehicle_model", self.vehicle_model.name().to_string()),
+            ("car_collisions", self.car_collisions.to_string()),
+            ("cars_in_rays", self.cars_in_rays.to_string()),
+            ("sensor_rays", self.sensors.rays.to_string()),
+            ("sensor_fov", self.sensors.fov.to_string()),
+            ("sensor_range", self.sensors.range.to_string()),
+            ("sensor_rear_rays", self.sensors.rear_rays.to_string()),
+            ("sensor_speed", self.sensors.speed.to_string()),
+            ("sensor_velocity", self.sensors.velocity.to_string()),
+            (
+                "sensor_local_velocity",
+                self.sensors.local_velocity.to_string(),
+            ),
+            ("sensor_acceleration", self.sensors.acceleration.to_string()),
+            ("sensor_steer", self.sensors.steer.to_string()),
+            ("sensor_heading", self.sensors.heading.to_string()),
+            (
+                "sensor_lookahead_points",
+                self.sensors.lookahead_points.to_string(),
+            ),
+            (
+                "sensor_lookahead_spacing",
+                self.sensors.lookahead_spacing.to_string(),
+            ),
+            ("sensor_track_offset", self.sensors.track_offset.to_string()),
+            (
+                "sensor_heading_error",
+                self.sensors.heading_error.to_string(),
+            ),
+            ("recurrent_layer", self.recurrent_layer.to_string()),
+            ("optimiser", self.optimiser.name().to_string()),
+            ("es_sigma", self.es_sigma.to_string()),
+            ("es_learning_rate", self.es_learning_rate.to_string()),
+            ("cma_sigma", self.cma_sigma.to_string()),
+            ("fitness", self.fitness.kind.name().to_string()),
+            ("novelty_search", self.novelty_search.to_string()),
+            ("novelty_weight", self.novelty_weight.to_string()),
+            ("novelty_samples", self.novelty_samples.to_string()),
+            ("novelty_neighbours", self.novelty_neighbours.to_string()),
+            (
+                "novelty_archive_rate",
+                self.novelty_archive_rate.to_string(),
+            ),
+            ("resume", self.resume.clone()),
+            ("sensor_noise", self.sensor_noise.to_string()),
+            ("ray_dropout", self.ray_dropout.to_string()),
+            ("actuator_latency", self.actuator_latency.to_string()),
+            ("scripted_drivers", self.scripted_drivers.to_string()),
+            ("baseline_driver", self.baseline_driver.to_string()),
+            ("race_laps", self.race_laps.to_string()),
+            ("race_tick_limit", self.race_tick_limit.to_string()),
+            ("race_brains", self.race_brains.join(", ")),
+            ("race_human", self.race_human.to_string()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+
+        // the weights are listed by their term names
+        for (name, weight) in TERM_NAMES.iter().zip(self.fitness.weights.iter()) {
+            settings.push((format!("fitness_{}", name), weight.to_string()));
+        }
+        return settings;
+    }
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    return value.parse::<T>().map_err(|e| e.to_string());
+}
+
+fn parse_positive<T: std::str::FromStr + PartialOrd + Default>(value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    // for settings that something gets divided by, NaN fails the comparison too
+    let parsed: T = parse(value)?;
+    if parsed > T::default() {
+        return Ok(parsed);
+    }
+    return Err("must be above 0".to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sensor_settings_must_be_positive() {
+        let mut config = RunConfig::default();
+        let defaults = config.sensors;
+        for value in ["0", "-5", "NaN"] {
+            config.set("sensor_range", value);
+        }
+        config.set("sensor_rays", "0");
+        assert_eq!(config.sensors.range, defaults.range);
+        assert_eq!(config.sensors.rays, defaults.rays);
+
+        config.set("sensor_range", "250");
+        config.set("sensor_rays", "7");
+        assert_eq!(config.sensors.range, 250.0);
+        assert_eq!(config.sensors.rays, 7);
+    }
+
+    #[test]
+    fn novelty_search_is_turned_off_with_nsga2() {
+        // either order of the two settings
+        let mut config = RunConfig::default();
+        config.set("novelty_search", "true");
+        config.set("optimiser", "nsga2");
+        assert!(!config.novelty_search);
+
+        let mut config = RunConfig::default();
+        config.set("optimiser", "nsga2");
+        config.set("novelty_search", "true");
+        assert!(!config.novelty_search);
+
+        let mut config = RunConfig::default();
+        config.set("novelty_search", "true");
+        assert!(config.novelty_search);
+    }
+}
@@ -0,0 +1,390 @@
+use crate::car::Car;
+use crate::network::Network;
+use crate::track::{Track, TrackPosition};
+use crate::vehicle::VehicleModel;
+use macroquad::prelude::*;
+use std::collections::VecDeque;
+
+// everything a controller is allowed to know about its car each tick
+pub struct Observation<'a> {
+    pub sensors: Vec<f64>, // the network inputs, ray distances then the motion terms
+    pub rays: Vec<f32>,    // ray distances normalised against the sensor range
+    pub position: Vec2,    // centre of the car
+    pub velocity: Vec2,
+    pub angle: f32,
+    pub track_position: TrackPosition,
+    pub track: &'a Track,
+    pub dt: f32,
+}
+
+// a network drives with one output for each of the controls
+pub const NETWORK_OUTPUTS: usize = 3;
+
+// what the controller wants the car to do
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Controls {
+    pub throttle: f32, // 0 to 1
+    pub steer: f32,    // -1 (left) to 1 (right)
+    pub brake: f32,    // 0 to 1
+}
+
+// something that drives a car, a network, a person, a recording...
+pub trait Controller {
+    fn control(&mut self, observation: &Observation) -> Controls;
+
+    // called when the car goes back to the start line
+    fn reset(&mut self) {}
+
+    // the network behind the controller, if it has one
+    fn network(&self) -> Option<&Network> {
+        return None;
+    }
+
+    fn name(&self) -> &str;
+
+    fn box_clone(&self) -> Box<dyn Controller>;
+}
+
+impl Clone for Box<dyn Controller> {
+    fn clone(&self) -> Self {
+        return self.box_clone();
+    }
+}
+
+#[derive(Clone)]
+pub struct NetworkController {
+    brain: Network,
+    latency: usize, // ticks between the network deciding and the car doing it
+    pending: VecDeque<Controls>, // decisions that haven't reached the car yet
+}
+
+impl NetworkController {
+    pub fn new(brain: Network) -> Self {
+        Self {
+            brain,
+            latency: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn with_latency(mut self, latency: usize) -> Self {
+        self.latency = latency;
+        return self;
+    }
+}
+
+impl Controller for NetworkController {
+    fn control(&mut self, observation: &Observation) -> Controls {
+        let outputs = self.brain.run(observation.sensors.clone());
+        let controls = Controls {
+            throttle: outputs[0] as f32,
+            steer: ((outputs[1] - 0.5) * 2.0) as f32, // convert to value between -1.0 and 1.0
+            brake: outputs[2] as f32,
+        };
+
+        // the car does nothing until the first decision comes through
+        self.pending.push_back(controls);
+        if self.pending.len() > self.latency {
+            return self.pending.pop_front().unwrap();
+        }
+        return Controls::default();
+    }
+
+    fn reset(&mut self) {
+        self.pending.clear();
+        self.brain.reset_state();
+    }
+
+    fn network(&self) -> Option<&Network> {
+        return Some(&self.brain);
+    }
+
+    fn name(&self) -> &str {
+        return "network";
+    }
+
+    fn box_clone(&self) -> Box<dyn Controller> {
+        return Box::new(self.clone());
+    }
+}
+
+#[derive(Clone)]
+pub struct KeyboardController;
+
+impl Controller for KeyboardController {
+    fn control(&mut self, _observation: &Observation) -> Controls {
+        let mut controls = Controls::default();
+
+        // loop through keys
+        for key in get_keys_down() {
+            if key == KeyCode::Up {
+                controls.throttle = 1.0;
+            }
+            if key == KeyCode::Down {
+                controls.brake = 1.0;
+            }
+            if key == KeyCode::Left {
+                controls.steer = -1.0;
+            }
+            if key == KeyCode::Right {
+                controls.steer = 1.0;
+            }
+        }
+        return controls;
+    }
+
+    fn name(&self) -> &str {
+        return "keyboard";
+    }
+
+    fn box_clone(&self) -> Box<dyn Controller> {
+        return Box::new(self.clone());
+    }
+}
+
+// plays back recorded controls one tick at a time
+#[derive(Clone)]
+pub struct ReplayController {
+    recording: Vec<Controls>,
+    tick: usize,
+}
+
+impl ReplayController {
+    pub fn new(recording: Vec<Controls>) -> Self {
+        Self { recording, tick: 0 }
+    }
+}
+
+impl Controller for ReplayController {
+    fn control(&mut self, _observation: &Observation) -> Controls {
+        // once the recording runs out the car just rolls to a stop
+        let controls = self.recording.get(self.tick).copied().unwrap_or_default();
+        self.tick += 1;
+        return controls;
+    }
+
+    fn reset(&mut self) {
+        self.tick = 0;
+    }
+
+    fn name(&self) -> &str {
+        return "replay";
+    }
+
+    fn box_clone(&self) -> Box<dyn Controller> {
+        return Box::new(self.clone());
+    }
+}
+
+// a simple hand written driver, steers towards the longest ray
+// and lifts off when there isn't much room in front
+#[derive(Clone)]
+pub struct ScriptedController;
+
+impl Controller for ScriptedController {
+    fn control(&mut self, observation: &Observation) -> Controls {
+        let rays = &observation.rays;
+        let middle = rays.len() / 2;
+
+        let mut longest = middle;
+        for i in 0..rays.len() {
+            if rays[i] > rays[longest] {
+                longest = i;
+            }
+        }
+
+        // rays go from left to right across the field of view
+        let steer = (longest as f32 - middle as f32) / middle.max(1) as f32;
+        let space_ahead = rays[middle] * 10.0; // roughly 1.0 is a car length or two away
+
+        return Controls {
+            throttle: clamp(space_ahead, 0.2, 1.0),
+            steer: clamp(steer * 2.0, -1.0, 1.0),
+            brake: if space_ahead < 0.5 { 0.5 } else { 0.0 },
+        };
+    }
+
+    fn name(&self) -> &str {
+        return "scripted";
+    }
+
+    fn box_clone(&self) -> Box<dyn Controller> {
+        return Box::new(self.clone());
+    }
+}
+
+// settings for the pure pursuit driver, all tunable from the config
+#[derive(Clone, Copy, Debug)]
+pub struct PursuitGains {
+    pub lookahead: f32,         // how far ahead on the centreline to aim for (px)
+    pub steer_gain: f32,        // scales the steering needed to follow the curve
+    pub max_speed: f32,         // fastest it will try to go (px/s)
+    pub lateral_accel: f32,     // how hard it is willing to corner (px/s^2)
+    pub braking_lookahead: f32, // how far ahead to look for corners to slow down for (px)
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+}
+
+impl Default for PursuitGains {
+    fn default() -> Self {
+        Self {
+            lookahead: 120.0,
+            steer_gain: 1.0,
+            max_speed: Car::MAX_SPEED,
+            lateral_accel: 500.0,
+            braking_lookahead: 200.0,
+            kp: 0.02,
+            ki: 0.005,
+            kd: 0.0005,
+        }
+    }
+}
+
+// a classical driver to compare the networks against
+// steers with pure pursuit along the centreline and holds a target speed with a pid
+#[derive(Clone)]
+pub struct PursuitController {
+    gains: PursuitGains,
+    vehicle_model: VehicleModel, // how much steering a curve needs depends on the physics
+    integral: f32,
+    prev_error: f32,
+}
+
+impl PursuitController {
+    pub fn new(gains: PursuitGains, vehicle_model: VehicleModel) -> Self {
+        Self {
+            gains,
+            vehicle_model,
+            integral: 0.0,
+            prev_error: 0.0,
+        }
+    }
+}
+
+impl Controller for PursuitController {
+    fn control(&mut self, observation: &Observation) -> Controls {
+        let gains = self.gains;
+        let track = observation.track;
+        let along = observation.track_position.distance_along;
+        let speed = observation.velocity.length();
+
+        // pure pursuit - aim for a point on the centreline ahead of the car
+        let target = track.point_at_distance(along + gains.lookahead);
+        let to_target =
+            (target - observation.position).rotate(Vec2::from_angle(-observation.angle));
+        let alpha = to_target.y.atan2(to_target.x); // angle to the target from the way we face
+        let curvature = 2.0 * alpha.sin() / to_target.length().max(1.0);
+
+        // steer enough to follow that curve at the current speed
+        let steer = gains.steer_gain
+            * self
+                .vehicle_model
+                .steer_for_curvature(curvature, speed.max(50.0));
+
+        // slow down for the sharpest bit of track coming up
+        let heading_now = track.direction_at_distance(along);
+        let heading_later = track.direction_at_distance(along + gains.braking_lookahead);
+        let upcoming_curvature =
+            heading_now.angle_between(heading_later).abs() / gains.braking_lookahead;
+        let mut target_speed = gains.max_speed;
+        if upcoming_curvature > f32::EPSILON {
+            target_speed = target_speed.min((gains.lateral_accel / upcoming_curvature).sqrt());
+        }
+
+        // pid on the speed, positive means accelerate and negative means brake
+        let dt = observation.dt.max(f32::EPSILON);
+        let error = target_speed - speed;
+        self.integral = clamp(self.integral + error * dt, -100.0, 100.0);
+        let derivative = (error - self.prev_error) / dt;
+        self.prev_error = error;
+        let output = gains.kp * error + gains.ki * self.integral + gains.kd * derivative;
+
+        return Controls {
+            throttle: clamp(output, 0.0, 1.0),
+            steer: clamp(steer, -1.0, 1.0),
+            brake: clamp(-output, 0.0, 1.0),
+        };
+    }
+
+    fn reset(&mut self) {
+        self.integral = 0.0;
+        self.prev_error = 0.0;
+    }
+
+    fn name(&self) -> &str {
+        return "pursuit";
+    }
+
+    fn box_clone(&self) -> Box<dyn Controller> {
+        return Box::new(self.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Layer;
+
+    fn square_track() -> Track {
+        let points = [
+            vec2(100.0, 100.0),
+            vec2(500.0, 100.0),
+            vec2(500.0, 500.0),
+            vec2(100.0, 500.0),
+        ];
+        return Track::new(&points, 50.0);
+    }
+
+    fn observation(track: &Track, input: f64) -> Observation<'_> {
+        return Observation {
+            sensors: vec![input],
+            rays: vec![],
+            position: vec2(300.0, 100.0),
+            velocity: Vec2::ZERO,
+            angle: 0.0,
+            track_position: TrackPosition::default(),
+            track,
+            dt: 1.0 / 60.0,
+        };
+    }
+
+    fn echo_controller(latency: usize) -> NetworkController {
+        // every output is the one input, so the throttle says which tick it was decided on
+        let layer = Layer::new(vec![vec![1.0]; NETWORK_OUTPUTS], vec![0.0; 3], None);
+        return NetworkController::new(Network::new_empty().add_layer(layer)).with_latency(latency);
+    }
+
+    fn throttles(controller: &mut NetworkController, track: &Track, ticks: usize) -> Vec<f32> {
+        // the input on each tick is 0.1 times the tick, starting at 0.1
+        let mut throttles = vec![];
+        for tick in 1..=ticks {
+            let controls = controller.control(&observation(track, tick as f64 / 10.0));
+            throttles.push((controls.throttle * 10.0).round());
+        }
+        return throttles;
+    }
+
+    #[test]
+    fn latency_delays_every_decision_by_that_many_ticks() {
+        let track = square_track();
+        let mut instant = echo_controller(0);
+        assert_eq!(throttles(&mut instant, &track, 4), [1.0, 2.0, 3.0, 4.0]);
+
+        // nothing happens until the first decision comes through
+        let mut delayed = echo_controller(3);
+        assert_eq!(
+            throttles(&mut delayed, &track, 7),
+            [0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 4.0]
+        );
+    }
+
+    #[test]
+    fn reset_forgets_decisions_still_on_the_way() {
+        let track = square_track();
+        let mut controller = echo_controller(2);
+        throttles(&mut controller, &track, 5);
+        controller.reset();
+        assert_eq!(throttles(&mut controller, &track, 4), [0.0, 0.0, 1.0, 2.0]);
+    }
+}
@@ -0,0 +1,205 @@
+// what a car is scored on, picked per run so the shaping can be tuned without recompiling
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FitnessKind {
+    Weighted, // the named terms times their weights, the defaults are the original scoring
+    Progress, // sectors and laps passed and nothing else
+    LapTime,  // how fast the best lap was, or how far round the first one it got
+    Distance, // distance along the centreline
+}
+
+impl FitnessKind {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        return match name {
+            "weighted" => Ok(Self::Weighted),
+            "progress" => Ok(Self::Progress),
+            "lap_time" => Ok(Self::LapTime),
+            "distance" => Ok(Self::Distance),
+            _ => Err("expected weighted, progress, lap_time or distance".to_string()),
+        };
+    }
+
+    pub fn name(&self) -> &str {
+        return match self {
+            Self::Weighted => "weighted",
+            Self::Progress => "progress",
+            Self::LapTime => "lap_time",
+            Self::Distance => "distance",
+        };
+    }
+}
+
+// the terms a weighted fitness can use, set in the config as fitness_<name> = weight
+pub const TERM_COUNT: usize = 9;
+pub const TERM_NAMES: [&str; TERM_COUNT] = [
+    "alive",
+    "sectors",
+    "laps",
+    "sector_speed",
+    "back_sectors",
+    "back_laps",
+    "crashed",
+    "average_speed",
+    "distance",
+];
+
+// the original constants, the default weighted fitness only differs from the old scoring
+// in not rounding each sector's speed and the average speed down to whole numbers
+const DEFAULT_WEIGHTS: [f64; TERM_COUNT] = [
+    1.0, 1000.0, 5000.0, 50000.0, -1000.0, -5000.0, -10000.0, 5.0, 0.0,
+];
+
+// a lap time fitness gets this for finishing a lap, so any lap beats getting most of the way
+const LAP_TIME_FINISHED: f64 = 1000.0;
+const LAP_TIME_SCALE: f64 = 1000000.0;
+
+// everything a car did that fitness can be made from
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct FitnessTerms {
+    pub alive: f64,   // ticks before it crashed
+    pub sectors: f64, // sectors passed going forwards
+    pub laps: f64,
+    pub sector_speed: f64,  // 1 / time^2 for every sector and lap, in ticks
+    pub back_sectors: f64,  // times it went back a sector
+    pub back_laps: f64,     // times it went back over the line
+    pub crashed: f64,       // 1 if it crashed
+    pub average_speed: f64, // px/s over the whole generation
+    pub distance: f64,      // along the centreline (px)
+    pub lap_fraction: f64,  // how far round the track the distance is
+    pub best_lap: Option<usize>,
+}
+
+impl FitnessTerms {
+    pub fn values(&self) -> [f64; TERM_COUNT] {
+        // in the same order as TERM_NAMES
+        return [
+            self.alive,
+            self.sectors,
+            self.laps,
+            self.sector_speed,
+            self.back_sectors,
+            self.back_laps,
+            self.crashed,
+            self.average_speed,
+            self.distance,
+        ];
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FitnessConfig {
+    pub kind: FitnessKind,
+    pub weights: [f64; TERM_COUNT], // for the weighted kind, in the same order as TERM_NAMES
+}
+
+impl Default for FitnessConfig {
+    fn default() -> Self {
+        Self {
+            kind: FitnessKind::Weighted,
+            weights: DEFAULT_WEIGHTS,
+        }
+    }
+}
+
+impl FitnessConfig {
+    pub fn set_weight(&mut self, name: &str, weight: f64) -> Result<(), String> {
+        match TERM_NAMES.iter().position(|&term| term == name) {
+            Some(i) => self.weights[i] = weight,
+            None => return Err(format!("unknown fitness term '{}'", name)),
+        }
+        return Ok(());
+    }
+
+    pub fn score(&self, terms: &FitnessTerms) -> f64 {
+        return match self.kind {
+            FitnessKind::Weighted => {
+                let values = terms.values();
+                let mut total = 0.0;
+                for i in 0..values.len() {
+                    total += self.weights[i] * values[i];
+                }
+                total
+            }
+            FitnessKind::Progress => {
+                terms.sectors + terms.laps - terms.back_sectors - terms.back_laps
+            }
+            FitnessKind::LapTime => match terms.best_lap {
+                Some(lap) => LAP_TIME_FINISHED + LAP_TIME_SCALE / lap.max(1) as f64,
+                None => LAP_TIME_FINISHED * terms.lap_fraction.clamp(0.0, 1.0),
+            },
+            FitnessKind::Distance => terms.distance,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms() -> FitnessTerms {
+        return FitnessTerms {
+            alive: 600.0,
+            sectors: 9.0,
+            laps: 1.0,
+            sector_speed: 0.002,
+            back_sectors: 2.0,
+            back_laps: 0.0,
+            crashed: 1.0,
+            average_speed: 120.5,
+            distance: 2500.0,
+            lap_fraction: 1.25,
+            best_lap: None,
+        };
+    }
+
+    #[test]
+    fn default_weights_add_up_the_terms() {
+        let config = FitnessConfig::default();
+        let expected = 600.0 + 9000.0 + 5000.0 + 100.0 - 2000.0 - 10000.0 + 602.5;
+        assert!((config.score(&terms()) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn set_weight_changes_only_that_term() {
+        let mut config = FitnessConfig::default();
+        assert!(config.set_weight("distance", 2.0).is_ok());
+        assert!(config.set_weight("crashed", 0.0).is_ok());
+        assert!(config.set_weight("speed", 1.0).is_err());
+
+        let mut expected = DEFAULT_WEIGHTS;
+        expected[8] = 2.0;
+        expected[6] = 0.0;
+        assert_eq!(config.weights, expected);
+
+        let before = FitnessConfig::default().score(&terms());
+        assert!((config.score(&terms()) - (before + 10000.0 + 5000.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn other_kinds_ignore_the_weights() {
+        let mut config = FitnessConfig::default();
+        config.set_weight("sectors", 0.0).unwrap();
+
+        config.kind = FitnessKind::Progress;
+        assert_eq!(config.score(&terms()), 8.0);
+        config.kind = FitnessKind::Distance;
+        assert_eq!(config.score(&terms()), 2500.0);
+    }
+
+    #[test]
+    fn any_finished_lap_beats_an_unfinished_one() {
+        let config = FitnessConfig {
+            kind: FitnessKind::LapTime,
+            weights: DEFAULT_WEIGHTS,
+        };
+        // further than a lap of distance but no lap counted, clamped to one lap
+        let unfinished = config.score(&terms());
+        assert_eq!(unfinished, LAP_TIME_FINISHED);
+
+        let mut slow = terms();
+        slow.best_lap = Some(100000);
+        let mut fast = terms();
+        fast.best_lap = Some(1000);
+        assert!(config.score(&slow) > unfinished);
+        assert!(config.score(&fast) > config.score(&slow));
+    }
+}
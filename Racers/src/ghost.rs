@@ -0,0 +1,160 @@
+use crate::car::Car;
+use crate::config::RunConfig;
+use crate::track::Track;
+use macroquad::prelude::*;
+use std::f32::consts::PI;
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+
+pub const GHOSTS_FOLDER: &str = "ghosts";
+const GHOST_COLOUR: Color = color_u8!(255, 255, 255, 90);
+
+// the fastest lap ever driven on a track, played back as a see through car
+// lap times only compare with the same smoothing and vehicle model, so each has its own ghost
+pub struct Ghost {
+    pub track_name: String,
+    pub smoothing: usize,
+    pub vehicle_model: String,
+    pub car_number: usize,
+    pub generation: usize,
+    pub lap_time: usize,
+    pub frames: Vec<(Vec2, f32)>, // centre and angle of the car every tick of the lap
+    texture: Texture2D,
+}
+
+impl Ghost {
+    pub fn from_car(car: &Car, track: &Track, config: &RunConfig, generation: usize) -> Self {
+        return Self {
+            track_name: track.name.clone(),
+            smoothing: track.get_resolution(),
+            vehicle_model: config.vehicle_model.name().to_string(),
+            car_number: car.number,
+            generation,
+            lap_time: car.lap_time,
+            frames: car.get_last_lap().to_vec(),
+            texture: Car::load_texture(),
+        };
+    }
+
+    pub fn path_for(track_name: &str, smoothing: usize, vehicle_model: &str) -> String {
+        return format!(
+            "{}/{}_{}_{}.ghost",
+            GHOSTS_FOLDER, track_name, smoothing, vehicle_model
+        );
+    }
+
+    pub fn load_for(track: &Track, config: &RunConfig) -> Result<Self, String> {
+        // the ghost driven with the same settings as this run
+        let smoothing = track.get_resolution();
+        let vehicle_model = config.vehicle_model.name();
+        let path = Self::path_for(&track.name, smoothing, vehicle_model);
+        let ghost = Self::load(&path)?;
+        if ghost.smoothing != smoothing || ghost.vehicle_model != vehicle_model {
+            return Err(format!("{}: driven with different settings", path));
+        }
+        return Ok(ghost);
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        // one ghost per track and settings, only ever replaced by a faster lap
+        fs::create_dir_all(GHOSTS_FOLDER)?;
+        let path = Self::path_for(&self.track_name, self.smoothing, &self.vehicle_model);
+        let mut file = File::create(path)?;
+
+        writeln!(file, "# racers ghost")?;
+        writeln!(file, "track = {}", self.track_name)?;
+        writeln!(file, "smoothing = {}", self.smoothing)?;
+        writeln!(file, "vehicle_model = {}", self.vehicle_model)?;
+        writeln!(file, "car = {}", self.car_number)?;
+        writeln!(file, "generation = {}", self.generation)?;
+        writeln!(file, "lap_time = {}", self.lap_time)?;
+
+        // then one line per tick: x, y, angle
+        writeln!(file, "frames")?;
+        for (position, angle) in self.frames.iter() {
+            writeln!(file, "{}, {}, {}", position.x, position.y, angle)?;
+        }
+
+        return Ok(());
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+        let mut ghost = Self {
+            track_name: String::new(),
+            smoothing: 1,
+            vehicle_model: String::new(),
+            car_number: 0,
+            generation: 0,
+            lap_time: 0,
+            frames: vec![],
+            texture: Car::load_texture(),
+        };
+
+        let mut in_frames = false;
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("{}:{}: {}", path, line_number + 1, message);
+
+            if in_frames {
+                let values: Vec<f32> = line
+                    .split(',')
+                    .map(|v| v.trim().parse::<f32>().map_err(|e| error(e.to_string())))
+                    .collect::<Result<_, _>>()?;
+                if values.len() != 3 {
+                    return Err(error("expected 'x, y, angle'".to_string()));
+                }
+                ghost.frames.push((vec2(values[0], values[1]), values[2]));
+                continue;
+            }
+
+            if line == "frames" {
+                in_frames = true;
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("unexpected line '{}'", line)));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let parse_error = |e: std::num::ParseIntError| error(e.to_string());
+
+            match key {
+                "track" => ghost.track_name = value.to_string(),
+                "smoothing" => ghost.smoothing = value.parse().map_err(parse_error)?,
+                "vehicle_model" => ghost.vehicle_model = value.to_string(),
+                "car" => ghost.car_number = value.parse().map_err(parse_error)?,
+                "generation" => ghost.generation = value.parse().map_err(parse_error)?,
+                "lap_time" => ghost.lap_time = value.parse().map_err(parse_error)?,
+                _ => return Err(error(format!("unknown key '{}'", key))),
+            }
+        }
+
+        if ghost.frames.is_empty() {
+            return Err(format!("{}: no frames", path));
+        }
+        return Ok(ghost);
+    }
+
+    pub fn draw(&self, tick: usize) {
+        // the ghost waits at the finish line once its lap is done
+        let (centre, angle) = self.frames[tick.min(self.frames.len() - 1)];
+
+        let params = DrawTextureParams {
+            dest_size: Some(Vec2::new(Car::HITBOX_WIDTH, Car::HITBOX_HEIGHT)),
+            source: None,
+            flip_x: false,
+            flip_y: false,
+            rotation: angle + PI / 2.0,
+            pivot: None,
+        };
+        let x = centre.x - Car::HITBOX_WIDTH / 2.0;
+        let y = centre.y - Car::HITBOX_HEIGHT / 2.0;
+        draw_texture_ex(&self.texture, x, y, GHOST_COLOUR, params);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::utils::ray_segment_intersection;
+use macroquad::prelude::*;
+
+// uniform grid over a set of line segments, so a ray only has to be tested
+// against the segments in the cells it passes through
+pub struct SegmentGrid {
+    segments: Vec<(Vec2, Vec2)>,
+    cells: Vec<Vec<usize>>, // indices into segments for each cell
+    origin: Vec2,           // top left corner of the grid
+    cell_size: f32,
+    cols: usize,
+    rows: usize,
+}
+
+impl SegmentGrid {
+    pub fn new(segments: Vec<(Vec2, Vec2)>, cell_size: f32) -> Self {
+        // find the bounding box of every segment
+        let mut min = Vec2::MAX;
+        let mut max = Vec2::MIN;
+        for (a, b) in segments.iter() {
+            min = min.min(a.min(*b));
+            max = max.max(a.max(*b));
+        }
+        if segments.is_empty() {
+            min = Vec2::ZERO;
+            max = Vec2::ZERO;
+        }
+
+        // pad by a cell so nothing sits exactly on the edge
+        let origin = min - Vec2::splat(cell_size);
+        let size = (max + Vec2::splat(cell_size)) - origin;
+        let cols = (size.x / cell_size).ceil().max(1.0) as usize;
+        let rows = (size.y / cell_size).ceil().max(1.0) as usize;
+
+        let mut grid = Self {
+            segments,
+            cells: vec![vec![]; cols * rows],
+            origin,
+            cell_size,
+            cols,
+            rows,
+        };
+
+        // add each segment to every cell its bounding box covers
+        // this may add a few cells the segment misses but keeps it simple
+        for i in 0..grid.segments.len() {
+            let (a, b) = grid.segments[i];
+            let (min_col, min_row) = grid.cell_of(a.min(b));
+            let (max_col, max_row) = grid.cell_of(a.max(b));
+
+            for row in min_row..=max_row {
+                for col in min_col..=max_col {
+                    grid.cells[row * cols + col].push(i);
+                }
+            }
+        }
+
+        return grid;
+    }
+
+    pub fn get_segments(&self) -> &[(Vec2, Vec2)] {
+        return &self.segments;
+    }
+
+    fn cell_of(&self, point: Vec2) -> (usize, usize) {
+        // which cell a point is in, clamped onto the grid
+        let local = (point - self.origin) / self.cell_size;
+        let col = clamp(local.x.floor(), 0.0, (self.cols - 1) as f32) as usize;
+        let row = clamp(local.y.floor(), 0.0, (self.rows - 1) as f32) as usize;
+        return (col, row);
+    }
+
+    pub fn cast_ray(&self, origin: Vec2, direction: Vec2, max_distance: f32) -> Option<f32> {
+        // returns the distance to the nearest segment hit by the ray
+        // direction must be normalised
+
+        // clip the ray to the bounds of the grid (slab method)
+        let grid_min = self.origin;
+        let grid_max = self.origin + vec2(self.cols as f32, self.rows as f32) * self.cell_size;
+        let mut t_enter: f32 = 0.0;
+        let mut t_exit: f32 = max_distance;
+
+        for axis in 0..2 {
+            let (o, d, lo, hi) = if axis == 0 {
+                (origin.x, direction.x, grid_min.x, grid_max.x)
+            } else {
+                (origin.y, direction.y, grid_min.y, grid_max.y)
+            };
+
+            if d.abs() < f32::EPSILON {
+                // parallel to this axis, must already be between the slabs
+                if o < lo || o > hi {
+                    return None;
+                }
+            } else {
+                let t1 = (lo - o) / d;
+                let t2 = (hi - o) / d;
+                t_enter = t_enter.max(t1.min(t2));
+                t_exit = t_exit.min(t1.max(t2));
+            }
+        }
+
+        if t_enter > t_exit {
+            return None;
+        }
+
+        // walk through the cells along the ray (amanatides & woo)
+        let start = origin + direction * t_enter;
+        let (mut col, mut row) = self.cell_of(start);
+
+        let step_col: i32 = if direction.x >= 0.0 { 1 } else { -1 };
+        let step_row: i32 = if direction.y >= 0.0 { 1 } else { -1 };
+
+        // distance along the ray to the next column / row boundary
+        let next_boundary = |cell: usize, step: i32, grid_start: f32| -> f32 {
+            let offset = if step > 0 { 1.0 } else { 0.0 };
+            return grid_start + (cell as f32 + offset) * self.cell_size;
+        };
+        let mut t_max_x = if direction.x.abs() < f32::EPSILON {
+            f32::MAX
+        } else {
+            (next_boundary(col, step_col, self.origin.x) - origin.x) / direction.x
+        };
+        let mut t_max_y = if direction.y.abs() < f32::EPSILON {
+            f32::MAX
+        } else {
+            (next_boundary(row, step_row, self.origin.y) - origin.y) / direction.y
+        };
+        let t_delta_x = self.cell_size / direction.x.abs().max(f32::EPSILON);
+        let t_delta_y = self.cell_size / direction.y.abs().max(f32::EPSILON);
+
+        let mut nearest: f32 = f32::MAX;
+
+        loop {
+            for segment_index in self.cells[row * self.cols + col].iter() {
+                let (a, b) = self.segments[*segment_index];
+                if let Some(t) = ray_segment_intersection(origin, direction, a, b) {
+                    if t < nearest && t <= max_distance {
+                        nearest = t;
+                    }
+                }
+            }
+
+            // anything hit inside this cell can't be beaten by a later cell
+            let cell_exit = t_max_x.min(t_max_y);
+            if nearest <= cell_exit || cell_exit > t_exit {
+                break;
+            }
+
+            // step into the next cell
+            if t_max_x < t_max_y {
+                let next = col as i32 + step_col;
+                if next < 0 || next >= self.cols as i32 {
+                    break;
+                }
+                col = next as usize;
+                t_max_x += t_delta_x;
+            } else {
+                let next = row as i32 + step_row;
+                if next < 0 || next >= self.rows as i32 {
+                    break;
+                }
+                row = next as usize;
+                t_max_y += t_delta_y;
+            }
+        }
+
+        if nearest == f32::MAX {
+            return None;
+        }
+        return Some(nearest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::rand::{gen_range, srand};
+
+    fn brute_force(
+        segments: &[(Vec2, Vec2)],
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+    ) -> Option<f32> {
+        // every segment checked, what the grid should agree with
+        let mut nearest: Option<f32> = None;
+        for (a, b) in segments.iter() {
+            if let Some(t) = ray_segment_intersection(origin, direction, *a, *b) {
+                if t <= max_distance && nearest.is_none_or(|n| t < n) {
+                    nearest = Some(t);
+                }
+            }
+        }
+        return nearest;
+    }
+
+    fn random_segments(count: usize) -> Vec<(Vec2, Vec2)> {
+        let mut segments = vec![];
+        for _ in 0..count {
+            let a = vec2(gen_range(0.0, 400.0), gen_range(0.0, 400.0));
+            let b = a + vec2(gen_range(-80.0, 80.0), gen_range(-80.0, 80.0));
+            segments.push((a, b.clamp(Vec2::ZERO, Vec2::splat(400.0))));
+        }
+        // the grid starts a cell before (0, 0) so these lie along the grid lines
+        segments.push((vec2(0.0, 0.0), vec2(0.0, 400.0)));
+        segments.push((vec2(0.0, 200.0), vec2(400.0, 200.0)));
+        return segments;
+    }
+
+    fn assert_matches(grid: &SegmentGrid, origin: Vec2, direction: Vec2, max_distance: f32) {
+        let direction = direction.normalize();
+        let expected = brute_force(grid.get_segments(), origin, direction, max_distance);
+        let found = grid.cast_ray(origin, direction, max_distance);
+        match (expected, found) {
+            (Some(e), Some(f)) => assert!(
+                (e - f).abs() < 1e-3,
+                "{:?} {:?}: expected {} found {}",
+                origin,
+                direction,
+                e,
+                f
+            ),
+            _ => assert_eq!(expected, found, "{:?} {:?}", origin, direction),
+        }
+    }
+
+    #[test]
+    fn random_rays_match_brute_force() {
+        srand(28);
+        let grid = SegmentGrid::new(random_segments(60), 40.0);
+        for _ in 0..2000 {
+            let origin = vec2(gen_range(-50.0, 450.0), gen_range(-50.0, 450.0));
+            let angle = gen_range(0.0, std::f32::consts::TAU);
+            assert_matches(
+                &grid,
+                origin,
+                Vec2::from_angle(angle),
+                gen_range(10.0, 600.0),
+            );
+        }
+    }
+
+    #[test]
+    fn axis_aligned_rays_match_brute_force() {
+        srand(29);
+        let grid = SegmentGrid::new(random_segments(60), 40.0);
+        let directions = [
+            vec2(1.0, 0.0),
+            vec2(-1.0, 0.0),
+            vec2(0.0, 1.0),
+            vec2(0.0, -1.0),
+        ];
+        for _ in 0..500 {
+            let origin = vec2(gen_range(0.0, 400.0), gen_range(0.0, 400.0));
+            for direction in directions {
+                assert_matches(&grid, origin, direction, 1000.0);
+            }
+        }
+    }
+
+    #[test]
+    fn rays_from_cell_boundaries_match_brute_force() {
+        srand(30);
+        let grid = SegmentGrid::new(random_segments(60), 40.0);
+        for _ in 0..500 {
+            // exactly on a corner between cells
+            let col = gen_range(0, grid.cols as i32) as f32;
+            let row = gen_range(0, grid.rows as i32) as f32;
+            let origin = grid.origin + vec2(col, row) * grid.cell_size;
+            let angle = gen_range(0.0, std::f32::consts::TAU);
+            assert_matches(&grid, origin, Vec2::from_angle(angle), 1000.0);
+            assert_matches(&grid, origin, vec2(1.0, 0.0), 1000.0);
+            assert_matches(&grid, origin, vec2(0.0, -1.0), 1000.0);
+        }
+    }
+
+    #[test]
+    fn rays_leaving_the_grid() {
+        let segments = vec![(vec2(100.0, 0.0), vec2(100.0, 100.0))];
+        let grid = SegmentGrid::new(segments, 40.0);
+
+        // pointing away from everything, and from outside pointing away
+        assert_eq!(
+            grid.cast_ray(vec2(50.0, 50.0), vec2(-1.0, 0.0), 1000.0),
+            None
+        );
+        assert_eq!(
+            grid.cast_ray(vec2(500.0, 50.0), vec2(1.0, 0.0), 1000.0),
+            None
+        );
+        assert_eq!(
+            grid.cast_ray(vec2(50.0, 50.0), vec2(0.0, 1.0), 1000.0),
+            None
+        );
+
+        // from outside the grid back into it
+        let hit = grid.cast_ray(vec2(-500.0, 50.0), vec2(1.0, 0.0), 1000.0);
+        assert!((hit.unwrap() - 600.0).abs() < 1e-3);
+        // too short to reach
+        assert_eq!(
+            grid.cast_ray(vec2(-500.0, 50.0), vec2(1.0, 0.0), 500.0),
+            None
+        );
+    }
+}
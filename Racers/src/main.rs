@@ -1,14 +1,34 @@
+// explicit returns and index loops are the style used throughout the project
+#![allow(
+    clippy::needless_return,
+    clippy::needless_range_loop,
+    clippy::new_without_default
+)]
+
 use app::App;
 use macroquad::prelude::*;
 
 pub mod app;
+pub mod brain;
 pub mod car;
+pub mod config;
+pub mod controller;
+pub mod fitness;
+pub mod ghost;
+pub mod grid;
 pub mod network;
+pub mod novelty;
+pub mod objectives;
+pub mod optimiser;
 pub mod population;
+pub mod race;
+pub mod replay;
+pub mod sensors;
 pub mod timer;
 pub mod track;
 pub mod ui;
 pub mod utils;
+pub mod vehicle;
 
 // constants
 pub const WINDOW_WIDTH: i32 = 1200;
@@ -27,8 +47,7 @@ fn window_conf() -> Conf {
 
 #[macroquad::main(window_conf)]
 async fn main() {
-    macroquad::rand::srand(macroquad::miniquad::date::now() as _);
-
+    // the random seed is set up by the app, it can come from the config
     let mut app = App::new();
 
     loop {
@@ -1,12 +1,75 @@
 use core::f64;
 use std::iter;
 
+// activation functions by name so networks can be saved
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Activation {
+    Sigmoid,
+    Tanh,
+}
+
+impl Activation {
+    pub fn apply(&self, x: f64) -> f64 {
+        return match self {
+            Self::Sigmoid => sigmoid(x),
+            Self::Tanh => x.tanh(),
+        };
+    }
+
+    pub fn from_name(name: &str) -> Result<Option<Self>, String> {
+        return match name {
+            "none" => Ok(None),
+            "sigmoid" => Ok(Some(Self::Sigmoid)),
+            "tanh" => Ok(Some(Self::Tanh)),
+            _ => Err(format!("unknown activation '{}'", name)),
+        };
+    }
+
+    pub fn name(activation: Option<Self>) -> &'static str {
+        return match activation {
+            None => "none",
+            Some(Self::Sigmoid) => "sigmoid",
+            Some(Self::Tanh) => "tanh",
+        };
+    }
+}
+
 #[derive(Clone)]
 pub struct Layer {
     pub weights: Vec<Vec<f64>>,
     pub bias: Vec<f64>,
-    activation: Option<fn(f64) -> f64>,
+    pub activation: Option<Activation>,
     pub output: Option<Vec<f64>>,
+
+    // elman style memory, the last outputs are fed back in with their own weights
+    pub recurrent_weights: Option<Vec<Vec<f64>>>, // outputs x outputs
+    pub state: Vec<f64>,
+}
+
+// the size of a layer, enough to find its parameters in a flattened network
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LayerShape {
+    pub inputs: usize,
+    pub outputs: usize,
+    pub recurrent: bool,
+}
+
+impl LayerShape {
+    pub fn weight_count(&self) -> usize {
+        return self.inputs * self.outputs;
+    }
+
+    pub fn recurrent_count(&self) -> usize {
+        if self.recurrent {
+            return self.outputs * self.outputs;
+        }
+        return 0;
+    }
+
+    pub fn param_count(&self) -> usize {
+        // weights, memory weights and then biases
+        return self.weight_count() + self.recurrent_count() + self.outputs;
+    }
 }
 
 #[derive(Clone)]
@@ -24,6 +87,68 @@ impl Network {
         return self;
     }
 
+    pub fn reset_state(&mut self) {
+        // forget everything the recurrent layers remember
+        for layer in self.layers.iter_mut() {
+            layer.reset_state();
+        }
+    }
+
+    pub fn layer_shapes(&self) -> Vec<LayerShape> {
+        return self.layers.iter().map(|layer| layer.shape()).collect();
+    }
+
+    pub fn param_count(&self) -> usize {
+        return self.layer_shapes().iter().map(|s| s.param_count()).sum();
+    }
+
+    pub fn flatten(&self) -> Vec<f64> {
+        // every parameter in one list, layer by layer in the same order as brain files
+        let mut params = vec![];
+        for layer in self.layers.iter() {
+            for row in layer.weights.iter() {
+                params.extend(row);
+            }
+            if let Some(recurrent_weights) = &layer.recurrent_weights {
+                for row in recurrent_weights.iter() {
+                    params.extend(row);
+                }
+            }
+            params.extend(&layer.bias);
+        }
+        return params;
+    }
+
+    pub fn unflatten(&self, params: &[f64]) -> Network {
+        // a network the same shape as this one with the parameters swapped in
+        // and nothing remembered
+        if params.len() != self.param_count() {
+            panic!("[network.rs] - Wrong number of parameters for this network!\n");
+        }
+
+        let mut network = self.clone();
+        let mut values = params.iter();
+        for layer in network.layers.iter_mut() {
+            for row in layer.weights.iter_mut() {
+                for weight in row.iter_mut() {
+                    *weight = *values.next().unwrap();
+                }
+            }
+            if let Some(recurrent_weights) = &mut layer.recurrent_weights {
+                for row in recurrent_weights.iter_mut() {
+                    for weight in row.iter_mut() {
+                        *weight = *values.next().unwrap();
+                    }
+                }
+            }
+            for bias in layer.bias.iter_mut() {
+                *bias = *values.next().unwrap();
+            }
+        }
+        network.reset_state();
+        return network;
+    }
+
     pub fn run(&mut self, inputs: Vec<f64>) -> Vec<f64> {
         // run first layer
         let mut prev_output: Vec<f64> = inputs;
@@ -35,21 +160,58 @@ impl Network {
 }
 
 impl Layer {
-    pub fn new(weights: Vec<Vec<f64>>, bias: Vec<f64>, activation: Option<fn(f64) -> f64>) -> Self {
+    pub fn new(weights: Vec<Vec<f64>>, bias: Vec<f64>, activation: Option<Activation>) -> Self {
         Self {
             weights,
             bias,
             activation,
             output: None,
+            recurrent_weights: None,
+            state: vec![],
         }
     }
 
-    pub fn new_random(inputs: usize, outputs: usize, activation: Option<fn(f64) -> f64>) -> Self {
+    pub fn with_recurrent_weights(mut self, recurrent_weights: Vec<Vec<f64>>) -> Self {
+        self.state = vec![0.0; recurrent_weights.len()];
+        self.recurrent_weights = Some(recurrent_weights);
+        return self;
+    }
+
+    pub fn new_recurrent(inputs: usize, outputs: usize, activation: Option<Activation>) -> Self {
+        // a random layer that also feeds its outputs back to itself next time
+        let mut recurrent_weights: Vec<Vec<f64>> = vec![];
+        for _ in 0..outputs {
+            let mut inner = vec![];
+            for _ in 0..outputs {
+                inner.push(macroquad::rand::gen_range(-0.75, 0.75));
+            }
+            recurrent_weights.push(inner);
+        }
+
+        let layer = Self::new_random(inputs, outputs, activation);
+        return layer.with_recurrent_weights(recurrent_weights);
+    }
+
+    pub fn shape(&self) -> LayerShape {
+        return LayerShape {
+            inputs: self.weights[0].len(),
+            outputs: self.bias.len(),
+            recurrent: self.recurrent_weights.is_some(),
+        };
+    }
+
+    pub fn reset_state(&mut self) {
+        for value in self.state.iter_mut() {
+            *value = 0.0;
+        }
+    }
+
+    pub fn new_random(inputs: usize, outputs: usize, activation: Option<Activation>) -> Self {
         // generating the weights between 0.75 and -0.75
         let mut weights: Vec<Vec<f64>> = vec![];
-        for i in 0..outputs {
+        for _ in 0..outputs {
             let mut inner = vec![];
-            for j in 0..inputs {
+            for _ in 0..inputs {
                 let val = macroquad::rand::gen_range(-0.75, 0.75);
                 inner.push(val);
             }
@@ -57,7 +219,7 @@ impl Layer {
         }
 
         let mut bias: Vec<f64> = vec![];
-        for i in 0..outputs {
+        for _ in 0..outputs {
             let val = macroquad::rand::gen_range(0.25, -0.25);
             bias.push(val);
         }
@@ -67,12 +229,12 @@ impl Layer {
 
     pub fn calculate(&mut self, inputs: Vec<f64>) -> Vec<f64> {
         // make sure that the inputs and weights are compatable sizes
-        if (inputs.len() != self.weights[0].len()) {
+        if inputs.len() != self.weights[0].len() {
             panic!("[network.rs] - Incompatable input and weights sizes!\n");
         }
 
         // create empty list of zeros
-        let mut outputs: Vec<f64> = iter::repeat(0.0).take(self.bias.len()).collect();
+        let mut outputs: Vec<f64> = iter::repeat_n(0.0, self.bias.len()).collect();
 
         // perform the matrix multiplication of inputs and weights
         for output_index in 0..outputs.len() {
@@ -85,9 +247,24 @@ impl Layer {
             outputs[output_index] += bias;
         }
 
-        // if there is an activation function return the values with the function applied
-        if (self.activation.is_some()) {
-            return self.apply_activation(outputs);
+        // add on the memory from last time
+        if let Some(recurrent_weights) = &self.recurrent_weights {
+            for output_index in 0..outputs.len() {
+                for state_index in 0..self.state.len() {
+                    let weight = recurrent_weights[output_index][state_index];
+                    outputs[output_index] += weight * self.state[state_index];
+                }
+            }
+        }
+
+        // if there is an activation function apply it to the values
+        if self.activation.is_some() {
+            outputs = self.apply_activation(outputs);
+        }
+
+        // remembered for next time
+        if self.recurrent_weights.is_some() {
+            self.state = outputs.clone();
         }
 
         self.output = Some(outputs.clone());
@@ -97,7 +274,7 @@ impl Layer {
 
     fn apply_activation(&self, mut inputs: Vec<f64>) -> Vec<f64> {
         for i in 0..inputs.len() {
-            inputs[i] = self.activation.unwrap()(inputs[i]);
+            inputs[i] = self.activation.unwrap().apply(inputs[i]);
         }
         return inputs;
     }
@@ -106,3 +283,115 @@ impl Layer {
 pub fn sigmoid(x: f64) -> f64 {
     return 1.0 / (1.0 + f64::consts::E.powf(-x));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_network() -> Network {
+        return Network::new_empty()
+            .add_layer(Layer::new_recurrent(4, 3, Some(Activation::Tanh)))
+            .add_layer(Layer::new_random(3, 2, Some(Activation::Sigmoid)));
+    }
+
+    #[test]
+    fn shapes_and_param_count() {
+        let network = test_network();
+        let shapes = network.layer_shapes();
+        assert_eq!(shapes.len(), 2);
+        assert_eq!(
+            shapes[0],
+            LayerShape {
+                inputs: 4,
+                outputs: 3,
+                recurrent: true
+            }
+        );
+        assert_eq!(shapes[1].recurrent_count(), 0);
+
+        // 4x3 + 3x3 + 3 then 3x2 + 2
+        assert_eq!(network.param_count(), 24 + 8);
+        assert_eq!(network.flatten().len(), network.param_count());
+    }
+
+    #[test]
+    fn new_recurrent_layer_shape() {
+        let layer = Layer::new_recurrent(5, 4, None);
+        assert_eq!(layer.weights.len(), 4);
+        assert_eq!(layer.weights[0].len(), 5);
+        let recurrent_weights = layer.recurrent_weights.as_ref().unwrap();
+        assert_eq!(recurrent_weights.len(), 4);
+        assert!(recurrent_weights.iter().all(|row| row.len() == 4));
+        // starts with nothing remembered
+        assert_eq!(layer.state, vec![0.0; 4]);
+    }
+
+    #[test]
+    fn recurrent_layer_remembers_last_output() {
+        // output = input + half of the last output
+        let mut layer =
+            Layer::new(vec![vec![1.0]], vec![0.0], None).with_recurrent_weights(vec![vec![0.5]]);
+        assert_eq!(layer.calculate(vec![1.0]), vec![1.0]);
+        assert_eq!(layer.calculate(vec![1.0]), vec![1.5]);
+        assert_eq!(layer.calculate(vec![1.0]), vec![1.75]);
+        assert_eq!(layer.calculate(vec![0.0]), vec![0.875]);
+
+        layer.reset_state();
+        assert_eq!(layer.calculate(vec![1.0]), vec![1.0]);
+
+        // a plain layer gives the same answer every time
+        let mut plain = Layer::new(vec![vec![1.0]], vec![0.0], None);
+        plain.calculate(vec![1.0]);
+        assert_eq!(plain.calculate(vec![1.0]), vec![1.0]);
+    }
+
+    #[test]
+    fn flatten_round_trip() {
+        let network = test_network();
+        let params = network.flatten();
+        let rebuilt = network.unflatten(&params);
+        assert_eq!(rebuilt.flatten(), params);
+        assert_eq!(rebuilt.layer_shapes(), network.layer_shapes());
+        for (a, b) in rebuilt.layers.iter().zip(network.layers.iter()) {
+            assert_eq!(a.weights, b.weights);
+            assert_eq!(a.recurrent_weights, b.recurrent_weights);
+            assert_eq!(a.bias, b.bias);
+            assert_eq!(a.activation, b.activation);
+        }
+    }
+
+    #[test]
+    fn unflatten_puts_params_back_in_order() {
+        let network = test_network();
+        let params: Vec<f64> = (0..network.param_count()).map(|i| i as f64).collect();
+        let rebuilt = network.unflatten(&params);
+        assert_eq!(rebuilt.flatten(), params);
+
+        // weights by row, then the memory weights, then the biases
+        let layer = &rebuilt.layers[0];
+        assert_eq!(layer.weights[1], vec![4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(
+            layer.recurrent_weights.as_ref().unwrap()[0],
+            vec![12.0, 13.0, 14.0]
+        );
+        assert_eq!(layer.bias, vec![21.0, 22.0, 23.0]);
+        assert_eq!(rebuilt.layers[1].weights[0], vec![24.0, 25.0, 26.0]);
+    }
+
+    #[test]
+    fn unflatten_forgets_state() {
+        let mut network = test_network();
+        network.run(vec![1.0; 4]);
+        assert!(network.layers[0].state.iter().any(|&s| s != 0.0));
+
+        let rebuilt = network.unflatten(&network.flatten());
+        assert!(rebuilt.layers[0].state.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn unflatten_wrong_length() {
+        let network = test_network();
+        network.unflatten(&[0.0; 3]);
+    }
+}
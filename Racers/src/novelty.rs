@@ -0,0 +1,148 @@
+use crate::brain::BRAINS_FOLDER;
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+pub const ARCHIVE_EXTENSION: &str = "archive";
+
+// behaviours seen in earlier generations, a car is novel if it ends up somewhere
+// neither they nor the rest of its generation went
+pub struct NoveltyArchive {
+    pub behaviours: Vec<Vec<f64>>,
+}
+
+impl NoveltyArchive {
+    pub fn new() -> Self {
+        Self { behaviours: vec![] }
+    }
+
+    pub fn path_for(name: &str) -> String {
+        // kept next to the brain of the same run so the run can carry on from it
+        return format!("{}/{}.{}", BRAINS_FOLDER, name, ARCHIVE_EXTENSION);
+    }
+
+    pub fn novelty(&self, index: usize, generation: &[Vec<f64>], neighbours: usize) -> f64 {
+        // average distance to the nearest behaviours, in the archive or the rest of the generation
+        let behaviour = &generation[index];
+        let mut distances: Vec<f64> = vec![];
+        for (i, other) in generation.iter().enumerate() {
+            if i != index {
+                distances.push(distance(behaviour, other));
+            }
+        }
+        for other in self.behaviours.iter() {
+            // behaviours from a run with different settings can't be compared
+            if other.len() == behaviour.len() {
+                distances.push(distance(behaviour, other));
+            }
+        }
+        if distances.is_empty() {
+            return 0.0;
+        }
+
+        distances.sort_by(|a, b| a.total_cmp(b));
+        distances.truncate(neighbours.max(1));
+        return distances.iter().sum::<f64>() / distances.len() as f64;
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        // make the folder the archive goes in, not always the brains folder
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(path)?;
+
+        // one behaviour per line
+        writeln!(file, "# racers novelty archive")?;
+        for behaviour in self.behaviours.iter() {
+            let values: Vec<String> = behaviour.iter().map(|v| v.to_string()).collect();
+            writeln!(file, "{}", values.join(", "))?;
+        }
+        return Ok(());
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+        let mut archive = Self::new();
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let behaviour: Vec<f64> = line
+                .split(',')
+                .map(|v| v.trim().parse::<f64>())
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("{}:{}: {}", path, line_number + 1, e))?;
+            archive.behaviours.push(behaviour);
+        }
+        return Ok(archive);
+    }
+}
+
+fn distance(a: &[f64], b: &[f64]) -> f64 {
+    return a
+        .iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f64>()
+        .sqrt();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn novelty_is_the_mean_distance_to_the_nearest() {
+        let generation = vec![vec![0.0, 0.0], vec![3.0, 4.0], vec![0.0, 1.0]];
+        let mut archive = NoveltyArchive::new();
+        archive.behaviours.push(vec![0.0, 2.0]);
+
+        // distances from the first car are 1, 2 and 5
+        assert_eq!(archive.novelty(0, &generation, 1), 1.0);
+        assert_eq!(archive.novelty(0, &generation, 2), 1.5);
+        assert_eq!(archive.novelty(0, &generation, 3), 8.0 / 3.0);
+        // more neighbours than there are behaviours uses them all
+        assert_eq!(archive.novelty(0, &generation, 10), 8.0 / 3.0);
+    }
+
+    #[test]
+    fn novelty_skips_behaviours_of_a_different_length() {
+        let generation = vec![vec![0.0, 0.0]];
+        let mut archive = NoveltyArchive::new();
+        assert_eq!(archive.novelty(0, &generation, 5), 0.0);
+
+        archive.behaviours.push(vec![0.0, 0.0, 0.0]);
+        archive.behaviours.push(vec![6.0, 8.0]);
+        assert_eq!(archive.novelty(0, &generation, 5), 10.0);
+    }
+
+    #[test]
+    fn load_reads_back_what_was_saved() {
+        let path = std::env::temp_dir().join("racers_test.archive");
+        let path = path.to_str().unwrap();
+        let mut archive = NoveltyArchive::new();
+        archive.behaviours.push(vec![1.5, -2.0, 0.25]);
+        archive.behaviours.push(vec![1e-7, 300.0, 42.0]);
+        archive.save(path).unwrap();
+
+        let loaded = NoveltyArchive::load(path).unwrap();
+        assert_eq!(loaded.behaviours, archive.behaviours);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn load_reports_the_bad_line() {
+        let path = std::env::temp_dir().join("racers_test_bad.archive");
+        let path = path.to_str().unwrap();
+        fs::write(path, "# racers novelty archive\n1, 2\n3, x\n").unwrap();
+
+        let error = NoveltyArchive::load(path).err().unwrap();
+        assert!(error.contains(":3:"), "{}", error);
+        assert!(NoveltyArchive::load("missing.archive").is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,252 @@
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+
+pub const PARETO_FILE: &str = "pareto_front.csv";
+pub const OBJECTIVE_COUNT: usize = 4;
+// steering from full lock one way to the other every tick
+pub const WORST_SMOOTHNESS: f64 = 2.0;
+
+// how a car did with the trade-offs kept apart instead of summed into one fitness
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Objectives {
+    pub progress: f64,   // distance along the track (px)
+    pub lap_time: f64,   // fastest lap (ticks), the time limit if it never finished one
+    pub smoothness: f64, // how much the steering moved per tick while moving, in full locks
+    pub survival: f64,   // fraction of the time limit driven without crashing
+}
+
+impl Objectives {
+    pub fn values(&self) -> [f64; OBJECTIVE_COUNT] {
+        // all the objectives as bigger is better
+        return [
+            self.progress,
+            -self.lap_time,
+            -self.smoothness,
+            self.survival,
+        ];
+    }
+
+    pub fn dominates(&self, other: &Objectives) -> bool {
+        // at least as good at everything and better at something
+        let (a, b) = (self.values(), other.values());
+        let mut better = false;
+        for i in 0..OBJECTIVE_COUNT {
+            if a[i] < b[i] {
+                return false;
+            }
+            if a[i] > b[i] {
+                better = true;
+            }
+        }
+        return better;
+    }
+}
+
+pub fn non_dominated_sort(objectives: &[Objectives]) -> Vec<Vec<usize>> {
+    // fronts of indices, nobody in a front is beaten by anyone in it or a later one
+    let size = objectives.len();
+    let mut beaten_by = vec![0; size];
+    let mut beats: Vec<Vec<usize>> = vec![vec![]; size];
+    for i in 0..size {
+        for j in 0..size {
+            if objectives[i].dominates(&objectives[j]) {
+                beats[i].push(j);
+            } else if objectives[j].dominates(&objectives[i]) {
+                beaten_by[i] += 1;
+            }
+        }
+    }
+
+    let mut fronts = vec![];
+    let mut front: Vec<usize> = (0..size).filter(|&i| beaten_by[i] == 0).collect();
+    while !front.is_empty() {
+        // take this front away and see who is left unbeaten
+        let mut next = vec![];
+        for &i in front.iter() {
+            for &j in beats[i].iter() {
+                beaten_by[j] -= 1;
+                if beaten_by[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+        fronts.push(front);
+        front = next;
+    }
+    return fronts;
+}
+
+pub fn crowding_distance(objectives: &[Objectives], front: &[usize]) -> Vec<f64> {
+    // how much room each member of a front has around it, the ends get infinity
+    // so the front stays spread out
+    let mut distance = vec![0.0; front.len()];
+    for m in 0..OBJECTIVE_COUNT {
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| {
+            let value_a = objectives[front[a]].values()[m];
+            let value_b = objectives[front[b]].values()[m];
+            value_a.total_cmp(&value_b)
+        });
+
+        // everyone the same tells us nothing
+        let value = |i: usize| objectives[front[order[i]]].values()[m];
+        let last = front.len() - 1;
+        let range = value(last) - value(0);
+        if range <= 0.0 {
+            continue;
+        }
+
+        distance[order[0]] = f64::INFINITY;
+        distance[order[last]] = f64::INFINITY;
+        for i in 1..last {
+            distance[order[i]] += (value(i + 1) - value(i - 1)) / range;
+        }
+    }
+    return distance;
+}
+
+pub fn save_front(generation: usize, objectives: &[Objectives]) -> std::io::Result<()> {
+    return save_front_to(PARETO_FILE, generation, objectives);
+}
+
+pub fn save_front_to(
+    path: &str,
+    generation: usize,
+    objectives: &[Objectives],
+) -> std::io::Result<()> {
+    // the cars of a generation nobody beat, added to the end of the file as csv
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    // a resumed run carries on the same file so only a new one gets the header
+    if file.metadata()?.len() == 0 {
+        writeln!(file, "generation,car,progress,lap_time,smoothness,survival")?;
+    }
+    let fronts = non_dominated_sort(objectives);
+    if let Some(front) = fronts.first() {
+        for &i in front.iter() {
+            let o = objectives[i];
+            writeln!(
+                file,
+                "{},{},{},{},{},{}",
+                generation,
+                i + 1,
+                o.progress,
+                o.lap_time,
+                o.smoothness,
+                o.survival
+            )?;
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn objectives(progress: f64, survival: f64) -> Objectives {
+        // only two objectives differ so the fronts are easy to work out
+        return Objectives {
+            progress,
+            lap_time: 1000.0,
+            smoothness: 0.1,
+            survival,
+        };
+    }
+
+    #[test]
+    fn dominance() {
+        let a = objectives(100.0, 1.0);
+        assert!(a.dominates(&objectives(50.0, 1.0)));
+        assert!(!a.dominates(&a));
+        assert!(!a.dominates(&objectives(200.0, 0.5)));
+        assert!(!objectives(200.0, 0.5).dominates(&a));
+
+        // lower lap time and smoothness are better
+        let mut faster = a;
+        faster.lap_time = 900.0;
+        assert!(faster.dominates(&a));
+        let mut jerkier = a;
+        jerkier.smoothness = 0.5;
+        assert!(a.dominates(&jerkier));
+    }
+
+    #[test]
+    fn sorts_into_fronts() {
+        let population = [
+            objectives(100.0, 1.0), // 0 front 1
+            objectives(300.0, 0.2), // 1 front 1
+            objectives(50.0, 0.9),  // 2 beaten by 0
+            objectives(200.0, 0.5), // 3 front 1
+            objectives(40.0, 0.1),  // 4 beaten by everyone
+            objectives(150.0, 0.4), // 5 beaten by 3
+        ];
+        let mut fronts = non_dominated_sort(&population);
+        for front in fronts.iter_mut() {
+            front.sort();
+        }
+        assert_eq!(fronts, vec![vec![0, 1, 3], vec![2, 5], vec![4]]);
+    }
+
+    #[test]
+    fn sorts_identical_cars_together() {
+        let population = [objectives(10.0, 1.0); 3];
+        assert_eq!(non_dominated_sort(&population), vec![vec![0, 1, 2]]);
+        assert!(non_dominated_sort(&[]).is_empty());
+    }
+
+    #[test]
+    fn crowding_distance_favours_the_ends_and_gaps() {
+        let population = [
+            objectives(0.0, 1.0),
+            objectives(10.0, 0.9),
+            objectives(90.0, 0.1),
+            objectives(100.0, 0.0),
+            objectives(50.0, 0.5),
+        ];
+        let front = [0, 1, 2, 3, 4];
+        let distance = crowding_distance(&population, &front);
+
+        assert_eq!(distance[0], f64::INFINITY);
+        assert_eq!(distance[3], f64::INFINITY);
+        // the middle car has the most room, its neighbours are 80 apart out of 100
+        // on progress and 0.8 out of 1 on survival
+        assert!((distance[4] - 1.6).abs() < 1e-9);
+        assert!((distance[1] - 1.0).abs() < 1e-9);
+        assert!(distance[4] > distance[1]);
+    }
+
+    #[test]
+    fn crowding_distance_ignores_objectives_that_are_all_the_same() {
+        // nobody is at an end when every car is the same
+        let population = [objectives(5.0, 1.0); 4];
+        let distance = crowding_distance(&population, &[0, 1, 2, 3]);
+        assert_eq!(distance, vec![0.0; 4]);
+    }
+
+    #[test]
+    fn front_header_is_written_once_per_file() {
+        let path = std::env::temp_dir().join("racers_test_front.csv");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        // a resumed run starts on a later generation but the new file still needs a header
+        let cars = [objectives(10.0, 1.0), objectives(5.0, 0.5)];
+        save_front_to(path, 7, &cars).unwrap();
+        save_front_to(path, 8, &cars).unwrap();
+
+        let text = std::fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            "generation,car,progress,lap_time,smoothness,survival"
+        );
+        assert_eq!(
+            lines.iter().filter(|l| l.starts_with("generation")).count(),
+            1
+        );
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("7,1,"));
+        assert!(lines[2].starts_with("8,1,"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
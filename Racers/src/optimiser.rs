@@ -0,0 +1,636 @@
+use crate::config::RunConfig;
+use crate::network::Network;
+use crate::objectives::{crowding_distance, non_dominated_sort, Objectives};
+use crate::utils::gaussian;
+use macroquad::rand::gen_range;
+
+// how the next generation of networks is made, picked per run so they can be compared
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OptimiserKind {
+    Genetic,           // the original, crossover of the best two and mutation
+    EvolutionStrategy, // openai style es, antithetic samples around a mean
+    CmaEs,             // samples from a gaussian that learns its own shape
+    Nsga2,             // multi-objective, parents picked by pareto front and crowding
+}
+
+impl OptimiserKind {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        return match name {
+            "genetic" => Ok(Self::Genetic),
+            "es" => Ok(Self::EvolutionStrategy),
+            "cmaes" => Ok(Self::CmaEs),
+            "nsga2" => Ok(Self::Nsga2),
+            _ => Err("expected genetic, es, cmaes or nsga2".to_string()),
+        };
+    }
+
+    pub fn name(&self) -> &str {
+        return match self {
+            Self::Genetic => "genetic",
+            Self::EvolutionStrategy => "es",
+            Self::CmaEs => "cmaes",
+            Self::Nsga2 => "nsga2",
+        };
+    }
+
+    pub fn label(&self) -> &str {
+        // for the menu button
+        return match self {
+            Self::Genetic => "GA",
+            Self::EvolutionStrategy => "OpenAI ES",
+            Self::CmaEs => "CMA-ES",
+            Self::Nsga2 => "NSGA-II",
+        };
+    }
+
+    pub fn next(&self) -> Self {
+        return match self {
+            Self::Genetic => Self::EvolutionStrategy,
+            Self::EvolutionStrategy => Self::CmaEs,
+            Self::CmaEs => Self::Nsga2,
+            Self::Nsga2 => Self::Genetic,
+        };
+    }
+}
+
+// how one car did, everything the optimisers can learn from
+pub struct Evaluation {
+    pub network: Network,
+    pub fitness: f64,
+    pub objectives: Objectives,
+}
+
+// every optimiser is told how a generation did and then asked for the next one
+pub trait Optimiser {
+    // how each car did, in the order the last ask gave them
+    fn tell(&mut self, evaluations: &[Evaluation]);
+    fn ask(&mut self, size: usize) -> Vec<Network>;
+}
+
+pub fn new_optimiser(config: &RunConfig) -> Box<dyn Optimiser> {
+    return match config.optimiser {
+        OptimiserKind::Genetic => Box::new(Genetic { parents: None }),
+        OptimiserKind::EvolutionStrategy => Box::new(EvolutionStrategy {
+            sigma: config.es_sigma,
+            learning_rate: config.es_learning_rate,
+            template: None,
+            mean: vec![],
+            noise: vec![],
+        }),
+        OptimiserKind::CmaEs => Box::new(CmaEs {
+            sigma: config.cma_sigma,
+            template: None,
+            mean: vec![],
+            covariance: vec![],
+            factor: vec![],
+            path_c: vec![],
+            path_sigma: vec![],
+            samples: vec![],
+            steps: vec![],
+            generation: 0,
+        }),
+        OptimiserKind::Nsga2 => Box::new(Nsga2 { parents: vec![] }),
+    };
+}
+
+fn ranked(evaluations: &[Evaluation]) -> Vec<usize> {
+    // indices from best to worst fitness, ties keep their order
+    let mut order: Vec<usize> = (0..evaluations.len()).collect();
+    order.sort_by(|&a, &b| evaluations[b].fitness.total_cmp(&evaluations[a].fitness));
+    return order;
+}
+
+// the original genetic algorithm, every child comes from the best two
+pub struct Genetic {
+    parents: Option<(Network, Network)>,
+}
+
+impl Optimiser for Genetic {
+    fn tell(&mut self, evaluations: &[Evaluation]) {
+        let order = ranked(evaluations);
+        let network1 = evaluations[order[0]].network.clone();
+        let network2 = evaluations[order[1]].network.clone();
+        self.parents = Some((network1, network2));
+    }
+
+    fn ask(&mut self, size: usize) -> Vec<Network> {
+        let (network1, network2) = self.parents.as_ref().unwrap();
+        let mut children = vec![];
+        for _ in 0..size {
+            children.push(reproduce(network1, network2));
+        }
+        return children;
+    }
+}
+
+fn reproduce(network1: &Network, network2: &Network) -> Network {
+    let params2 = network2.flatten();
+    let mut child = network1.flatten();
+
+    let mut start = 0;
+    for shape in network1.layer_shapes() {
+        let weights = start..start + shape.weight_count();
+        let recurrent = weights.end..weights.end + shape.recurrent_count();
+        let biases = recurrent.end..recurrent.end + shape.outputs;
+        start = biases.end;
+
+        // apply cross over, the weights, memory weights and biases each get their own point
+        for section in [weights.clone(), recurrent.clone(), biases.clone()] {
+            if section.is_empty() {
+                continue;
+            }
+            let crossover = section.start + gen_range(0, section.len() - 1);
+            child[section.start..=crossover].copy_from_slice(&params2[section.start..=crossover]);
+        }
+
+        // apply mutations
+        mutate_weights(&mut child[weights]);
+        mutate_weights(&mut child[recurrent]);
+        for bias in child[biases].iter_mut() {
+            if (gen_range(0.0, 1.0)) <= 0.02 {
+                *bias = gen_range(-0.5, 0.5);
+            }
+            if gen_range(0.0, 1.0) <= 0.03 {
+                *bias += gen_range(-0.5, 0.5);
+            }
+        }
+    }
+
+    return network1.unflatten(&child);
+}
+
+pub fn variations(network: &Network, size: usize) -> Vec<Network> {
+    // a first generation grown from one network, itself and then mutated copies
+    let mut networks = vec![];
+    for i in 0..size {
+        if i == 0 {
+            networks.push(network.unflatten(&network.flatten()));
+        } else {
+            networks.push(reproduce(network, network));
+        }
+    }
+    return networks;
+}
+
+fn mutate_weights(weights: &mut [f64]) {
+    for weight in weights.iter_mut() {
+        if gen_range(0.0, 1.0) <= 0.02 {
+            *weight = gen_range(-1.0, 1.0);
+        }
+        if gen_range(0.0, 1.0) <= 0.03 {
+            *weight += gen_range(-0.5, 0.5);
+        }
+    }
+}
+// nsga-ii, the objectives are kept apart and the parents are the cars nobody beat
+// with the most room around them, children come from tournaments between parents
+pub struct Nsga2 {
+    parents: Vec<Parent>,
+}
+
+struct Parent {
+    network: Network,
+    objectives: Objectives,
+    rank: usize, // which front it was in
+    crowding: f64,
+}
+
+impl Nsga2 {
+    fn tournament(&self) -> &Network {
+        // the better of two random parents, lower front first then more room
+        let a = &self.parents[gen_range(0, self.parents.len())];
+        let b = &self.parents[gen_range(0, self.parents.len())];
+        if a.rank < b.rank || (a.rank == b.rank && a.crowding > b.crowding) {
+            return &a.network;
+        }
+        return &b.network;
+    }
+}
+
+impl Optimiser for Nsga2 {
+    fn tell(&mut self, evaluations: &[Evaluation]) {
+        // the last parents compete with their children so the best are never lost
+        let mut pool: Vec<(Network, Objectives)> = std::mem::take(&mut self.parents)
+            .into_iter()
+            .map(|p| (p.network, p.objectives))
+            .collect();
+        for evaluation in evaluations.iter() {
+            pool.push((evaluation.network.clone(), evaluation.objectives));
+        }
+        let objectives: Vec<Objectives> = pool.iter().map(|(_, o)| *o).collect();
+
+        // fill up from the best front down, the front that doesn't fit keeps its most spread out
+        let size = evaluations.len();
+        let mut chosen: Vec<(usize, usize, f64)> = vec![];
+        for (rank, front) in non_dominated_sort(&objectives).iter().enumerate() {
+            if chosen.len() >= size {
+                break;
+            }
+            let crowding = crowding_distance(&objectives, front);
+            let mut members: Vec<(usize, usize, f64)> = front
+                .iter()
+                .zip(crowding)
+                .map(|(&i, distance)| (i, rank, distance))
+                .collect();
+            members.sort_by(|a, b| b.2.total_cmp(&a.2));
+            members.truncate(size - chosen.len());
+            chosen.extend(members);
+        }
+
+        let mut pool: Vec<Option<(Network, Objectives)>> = pool.into_iter().map(Some).collect();
+        for (i, rank, crowding) in chosen {
+            let (network, objectives) = pool[i].take().unwrap();
+            self.parents.push(Parent {
+                network,
+                objectives,
+                rank,
+                crowding,
+            });
+        }
+    }
+
+    fn ask(&mut self, size: usize) -> Vec<Network> {
+        let mut children = vec![];
+        for _ in 0..size {
+            children.push(reproduce(self.tournament(), self.tournament()));
+        }
+        return children;
+    }
+}
+
+// openai style evolution strategy
+// the cars are the mean plus and minus the same noise, and the mean follows
+// the noise weighted by each car's rank rather than its raw fitness
+pub struct EvolutionStrategy {
+    sigma: f64,
+    learning_rate: f64,
+    template: Option<Network>,
+    mean: Vec<f64>,
+    noise: Vec<Vec<f64>>, // what each car of the last generation was given
+}
+
+impl Optimiser for EvolutionStrategy {
+    fn tell(&mut self, evaluations: &[Evaluation]) {
+        // the first generation is random so start from the best of it
+        if self.template.is_none() || self.noise.len() != evaluations.len() {
+            let best = &evaluations[ranked(evaluations)[0]].network;
+            self.mean = best.flatten();
+            self.template = Some(best.clone());
+            return;
+        }
+
+        // fitness shaping, ranks spread evenly from -0.5 for the worst to 0.5 for the best
+        let size = evaluations.len();
+        let mut shaped = vec![0.0; size];
+        if size > 1 {
+            for (rank, &i) in ranked(evaluations).iter().enumerate() {
+                shaped[i] = 0.5 - rank as f64 / (size - 1) as f64;
+            }
+        }
+
+        // estimate of the gradient, the mirrored pairs cancel out everything but the difference
+        let scale = self.learning_rate / (size as f64 * self.sigma);
+        for i in 0..size {
+            for (param, noise) in self.mean.iter_mut().zip(self.noise[i].iter()) {
+                *param += scale * shaped[i] * noise;
+            }
+        }
+    }
+
+    fn ask(&mut self, size: usize) -> Vec<Network> {
+        let template = self.template.as_ref().unwrap();
+
+        // antithetic sampling, each noise vector is used both ways
+        self.noise.clear();
+        for _ in 0..size / 2 {
+            let noise: Vec<f64> = self.mean.iter().map(|_| gaussian(1.0) as f64).collect();
+            self.noise.push(noise.iter().map(|n| -n).collect());
+            self.noise.push(noise);
+        }
+        // an odd one out just drives the mean
+        if size % 2 == 1 {
+            self.noise.push(vec![0.0; self.mean.len()]);
+        }
+
+        let mut networks = vec![];
+        for noise in self.noise.iter() {
+            let params: Vec<f64> = self
+                .mean
+                .iter()
+                .zip(noise.iter())
+                .map(|(m, n)| m + self.sigma * n)
+                .collect();
+            networks.push(template.unflatten(&params));
+        }
+        return networks;
+    }
+}
+
+// covariance matrix adaptation evolution strategy
+// samples from a gaussian around the mean and reshapes it towards the directions
+// the best cars moved in, the cholesky factor stands in for the square root of the
+// covariance so there's no eigen decomposition every generation
+pub struct CmaEs {
+    sigma: f64,
+    template: Option<Network>,
+    mean: Vec<f64>,
+    covariance: Vec<Vec<f64>>,
+    factor: Vec<Vec<f64>>, // lower triangular, factor * factor^T = covariance
+    path_c: Vec<f64>,
+    path_sigma: Vec<f64>,
+    samples: Vec<Vec<f64>>, // the standard normal draws for each car
+    steps: Vec<Vec<f64>>,   // the same draws shaped by the covariance
+    generation: usize,
+}
+
+impl CmaEs {
+    fn start(&mut self, network: &Network) {
+        self.mean = network.flatten();
+        let n = self.mean.len();
+        self.covariance = identity(n);
+        self.factor = identity(n);
+        self.path_c = vec![0.0; n];
+        self.path_sigma = vec![0.0; n];
+        self.template = Some(network.clone());
+    }
+}
+
+impl Optimiser for CmaEs {
+    fn tell(&mut self, evaluations: &[Evaluation]) {
+        // the first generation is random so start from the best of it
+        let order = ranked(evaluations);
+        if self.template.is_none() || self.samples.len() != evaluations.len() {
+            self.start(&evaluations[order[0]].network);
+            return;
+        }
+        self.generation += 1;
+
+        // the usual settings, all from the number of parameters and cars
+        let n = self.mean.len() as f64;
+        let parents = (evaluations.len() / 2).max(1);
+        let mut weights: Vec<f64> = (0..parents)
+            .map(|i| (parents as f64 + 0.5).ln() - ((i + 1) as f64).ln())
+            .collect();
+        let total: f64 = weights.iter().sum();
+        for weight in weights.iter_mut() {
+            *weight /= total;
+        }
+        let mu_eff = 1.0 / weights.iter().map(|w| w * w).sum::<f64>();
+
+        let cc = (4.0 + mu_eff / n) / (n + 4.0 + 2.0 * mu_eff / n);
+        let cs = (mu_eff + 2.0) / (n + mu_eff + 5.0);
+        let c1 = 2.0 / ((n + 1.3) * (n + 1.3) + mu_eff);
+        let cmu =
+            (1.0 - c1).min(2.0 * (mu_eff - 2.0 + 1.0 / mu_eff) / ((n + 2.0) * (n + 2.0) + mu_eff));
+        let damps = 1.0 + 2.0 * (((mu_eff - 1.0) / (n + 1.0)).sqrt() - 1.0).max(0.0) + cs;
+        let chi_n = n.sqrt() * (1.0 - 1.0 / (4.0 * n) + 1.0 / (21.0 * n * n));
+
+        // weighted average of the best steps, in both spaces
+        let size = self.mean.len();
+        let mut step_w = vec![0.0; size];
+        let mut sample_w = vec![0.0; size];
+        for (weight, &i) in weights.iter().zip(order.iter()) {
+            for j in 0..size {
+                step_w[j] += weight * self.steps[i][j];
+                sample_w[j] += weight * self.samples[i][j];
+            }
+        }
+
+        // move the mean
+        for j in 0..size {
+            self.mean[j] += self.sigma * step_w[j];
+        }
+
+        // evolution paths, where the mean has been heading lately
+        let norm_s = (cs * (2.0 - cs) * mu_eff).sqrt();
+        for j in 0..size {
+            self.path_sigma[j] = (1.0 - cs) * self.path_sigma[j] + norm_s * sample_w[j];
+        }
+        let path_length = self.path_sigma.iter().map(|p| p * p).sum::<f64>().sqrt();
+        let decay = 1.0 - (1.0 - cs).powi(2 * self.generation as i32);
+        let steady = path_length / decay.sqrt() < (1.4 + 2.0 / (n + 1.0)) * chi_n;
+        let h_sigma = steady as i32 as f64;
+
+        let norm_c = (cc * (2.0 - cc) * mu_eff).sqrt();
+        for j in 0..size {
+            self.path_c[j] = (1.0 - cc) * self.path_c[j] + h_sigma * norm_c * step_w[j];
+        }
+
+        // reshape the covariance, rank one from the path and rank mu from the best steps
+        let keep = 1.0 - c1 - cmu + c1 * (1.0 - h_sigma) * cc * (2.0 - cc);
+        for a in 0..size {
+            for b in 0..=a {
+                let mut value = keep * self.covariance[a][b];
+                value += c1 * self.path_c[a] * self.path_c[b];
+                for (weight, &i) in weights.iter().zip(order.iter()) {
+                    value += cmu * weight * self.steps[i][a] * self.steps[i][b];
+                }
+                self.covariance[a][b] = value;
+                self.covariance[b][a] = value;
+            }
+        }
+
+        // longer paths than chance would give mean bigger steps
+        self.sigma *= ((cs / damps) * (path_length / chi_n - 1.0)).exp();
+
+        match cholesky(&self.covariance) {
+            Some(factor) => self.factor = factor,
+            None => {
+                println!("[optimiser] - Covariance lost its shape, starting it again");
+                self.covariance = identity(size);
+                self.factor = identity(size);
+            }
+        }
+    }
+
+    fn ask(&mut self, size: usize) -> Vec<Network> {
+        let template = self.template.as_ref().unwrap();
+        let n = self.mean.len();
+
+        self.samples.clear();
+        self.steps.clear();
+        let mut networks = vec![];
+        for _ in 0..size {
+            let sample: Vec<f64> = (0..n).map(|_| gaussian(1.0) as f64).collect();
+            let mut step = vec![0.0; n];
+            for a in 0..n {
+                for b in 0..=a {
+                    step[a] += self.factor[a][b] * sample[b];
+                }
+            }
+            let params: Vec<f64> = (0..n)
+                .map(|j| self.mean[j] + self.sigma * step[j])
+                .collect();
+            networks.push(template.unflatten(&params));
+            self.samples.push(sample);
+            self.steps.push(step);
+        }
+        return networks;
+    }
+}
+
+fn identity(size: usize) -> Vec<Vec<f64>> {
+    let mut matrix = vec![vec![0.0; size]; size];
+    for i in 0..size {
+        matrix[i][i] = 1.0;
+    }
+    return matrix;
+}
+
+fn cholesky(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    // lower triangular l with l * l^T = matrix, none if it isn't positive definite
+    let size = matrix.len();
+    let mut l = vec![vec![0.0; size]; size];
+    for i in 0..size {
+        for j in 0..=i {
+            let mut sum = matrix[i][j];
+            for k in 0..j {
+                sum -= l[i][k] * l[j][k];
+            }
+            if i == j {
+                if sum <= 0.0 {
+                    return None;
+                }
+                l[i][i] = sum.sqrt();
+            } else {
+                l[i][j] = sum / l[j][j];
+            }
+        }
+    }
+    return Some(l);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Layer;
+    use macroquad::rand::srand;
+
+    fn tiny_network(inputs: usize, outputs: usize) -> Network {
+        let layer = Layer::new(vec![vec![0.0; inputs]; outputs], vec![0.0; outputs], None);
+        return Network::new_empty().add_layer(layer);
+    }
+
+    fn evaluate(networks: Vec<Network>, fitness: impl Fn(&[f64]) -> f64) -> Vec<Evaluation> {
+        return networks
+            .into_iter()
+            .map(|network| Evaluation {
+                fitness: fitness(&network.flatten()),
+                network,
+                objectives: Objectives::default(),
+            })
+            .collect();
+    }
+
+    fn error(params: &[f64], target: &[f64]) -> f64 {
+        return params
+            .iter()
+            .zip(target.iter())
+            .map(|(p, t)| (p - t) * (p - t))
+            .sum();
+    }
+
+    #[test]
+    fn cholesky_factors_rebuild_the_matrix() {
+        let matrix = vec![
+            vec![4.0, 12.0, -16.0],
+            vec![12.0, 37.0, -43.0],
+            vec![-16.0, -43.0, 98.0],
+        ];
+        let l = cholesky(&matrix).unwrap();
+        assert_eq!(
+            l,
+            vec![
+                vec![2.0, 0.0, 0.0],
+                vec![6.0, 1.0, 0.0],
+                vec![-8.0, 5.0, 3.0]
+            ]
+        );
+        for i in 0..3 {
+            for j in 0..3 {
+                let value: f64 = (0..3).map(|k| l[i][k] * l[j][k]).sum();
+                assert!((value - matrix[i][j]).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn cholesky_rejects_matrices_that_arent_positive_definite() {
+        assert!(cholesky(&[vec![1.0, 2.0], vec![2.0, 1.0]]).is_none());
+        assert!(cholesky(&[vec![1.0, 0.0], vec![0.0, 0.0]]).is_none());
+        assert!(cholesky(&[vec![-1.0]]).is_none());
+        assert!(cholesky(&identity(4)).is_some());
+    }
+
+    #[test]
+    fn es_moves_the_mean_towards_the_better_sample() {
+        srand(46);
+        let mut es = EvolutionStrategy {
+            sigma: 0.1,
+            learning_rate: 0.05,
+            template: None,
+            mean: vec![],
+            noise: vec![],
+        };
+        let start = tiny_network(2, 1);
+        es.tell(&evaluate(vec![start.clone(), start], |_| 0.0));
+        let mean = es.mean.clone();
+
+        // one antithetic pair, the one with a positive first parameter does better
+        let samples = es.ask(2);
+        let evaluations = evaluate(samples, |params| params[0]);
+        let better = &evaluations[ranked(&evaluations)[0]].network.flatten();
+        es.tell(&evaluations);
+
+        // the mirrored pair adds up to the better one's noise
+        let scale = es.learning_rate / (2.0 * es.sigma * es.sigma);
+        for j in 0..mean.len() {
+            let expected = mean[j] + scale * (better[j] - mean[j]);
+            assert!((es.mean[j] - expected).abs() < 1e-9);
+        }
+        assert!(es.mean[0] > mean[0]);
+    }
+
+    #[test]
+    fn cmaes_finds_the_bottom_of_a_quadratic() {
+        srand(47);
+        let target = [0.8, -0.5, 0.3, 0.1, -0.9, 0.6];
+        let fitness = |params: &[f64]| -error(params, &target);
+        let mut cmaes = CmaEs {
+            sigma: 0.3,
+            template: None,
+            mean: vec![],
+            covariance: vec![],
+            factor: vec![],
+            path_c: vec![],
+            path_sigma: vec![],
+            samples: vec![],
+            steps: vec![],
+            generation: 0,
+        };
+
+        // two inputs and two outputs is six parameters, all starting at 0
+        cmaes.tell(&evaluate(vec![tiny_network(2, 2); 12], fitness));
+        let start_error = error(&cmaes.mean, &target);
+        for _ in 0..80 {
+            let samples = cmaes.ask(12);
+            cmaes.tell(&evaluate(samples, fitness));
+        }
+
+        let end_error = error(&cmaes.mean, &target);
+        assert!(
+            end_error < start_error * 1e-3,
+            "{} -> {}",
+            start_error,
+            end_error
+        );
+        // closing in shrinks the steps, and the covariance stays a covariance
+        assert!(cmaes.sigma < 0.3, "{}", cmaes.sigma);
+        for a in 0..target.len() {
+            for b in 0..target.len() {
+                assert_eq!(cmaes.covariance[a][b], cmaes.covariance[b][a]);
+            }
+        }
+        assert!(cholesky(&cmaes.covariance).is_some());
+    }
+}
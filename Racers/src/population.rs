@@ -1,53 +1,174 @@
+use crate::brain::SavedBrain;
 use crate::car::*;
+use crate::config::RunConfig;
+use crate::controller::{PursuitController, ScriptedController};
+use crate::ghost::Ghost;
+use crate::novelty::NoveltyArchive;
+use crate::objectives::save_front;
+use crate::optimiser::{new_optimiser, variations, Evaluation, Optimiser, OptimiserKind};
+use crate::replay::Recording;
 use crate::timer::*;
 use crate::track::*;
 use crate::{WINDOW_HEIGHT, WINDOW_WIDTH};
 use macroquad::prelude::*;
-use macroquad::rand::gen_range;
 use std::fs::File;
 use std::io::prelude::*;
-use std::time;
+
+const SCRIPTED_COLOUR: Color = color_u8!(255, 150, 60, 255);
+const BASELINE_COLOUR: Color = color_u8!(200, 110, 255, 255);
 
 pub struct Population {
     generation: usize,
     cars: Vec<Car>,
+    drivers: Vec<Car>, // cars racing alongside that aren't part of the evolution
     track: Track,
     ticks: u32,
     timer: Timer,
     data_file: File,
     time_limit: u32,
+    config: RunConfig,
+    ghost: Option<Ghost>, // fastest lap on this track, from this run or an earlier one
+    replay_lap: Option<usize>, // the lap the saved replay ends with, only a faster one replaces it
+    brain_fitness: Option<f64>, // fitness of the saved brain, only a fitter one replaces it
+    optimiser: Box<dyn Optimiser>,
+
+    // novelty search
+    behaviours: Vec<Vec<f64>>, // where each car has been so far this generation
+    archive: NoveltyArchive,
 }
 
 impl Population {
-    pub fn new(size: usize, time_limit: u32) -> Self {
-        let track: Track = Track::new(test_track1, 100.0);
+    pub fn new(
+        size: usize,
+        time_limit: u32,
+        track: Track,
+        mut config: RunConfig,
+        human: bool,
+    ) -> Self {
+        // carry on from a saved brain, along with the novelty archive saved next to it
+        let mut resumed: Option<SavedBrain> = None;
+        let mut archive = NoveltyArchive::new();
+        if !config.resume.is_empty() {
+            match SavedBrain::by_name(&config.resume) {
+                Ok(brain) => {
+                    // the network only works with the sensors it was trained with
+                    config.sensors = brain.sensors();
+                    if config.novelty_search {
+                        let path = NoveltyArchive::path_for(&config.resume);
+                        match NoveltyArchive::load(&path) {
+                            Ok(saved) => archive = saved,
+                            Err(e) => println!("Could not load novelty archive - {}", e),
+                        }
+                    }
+                    resumed = Some(brain);
+                }
+                Err(e) => println!("Could not resume - {}", e),
+            }
+        }
+
+        // show the settings being used so runs can be told apart
+        println!("Run settings:");
+        for (key, value) in config.describe() {
+            println!("  {} = {}", key, value);
+        }
+
         let mut cars = vec![];
-        for i in 0..size {
-            // set car numbers as i+1, e.g. first car will get number 1
-            cars.push(Car::new(track.get_start_pos(), i + 1));
+        let mut generation = 0;
+        if let Some(brain) = &resumed {
+            println!(
+                "Resuming from {} - generation {}, {} novelty behaviours",
+                brain.name,
+                brain.generation,
+                archive.behaviours.len()
+            );
+            generation = brain.generation + 1;
+            for (i, network) in variations(&brain.network, size).into_iter().enumerate() {
+                cars.push(Car::with_brain(
+                    track.get_start_pos(),
+                    i + 1,
+                    network,
+                    &config,
+                ));
+            }
+        } else {
+            for i in 0..size {
+                // set car numbers as i+1, e.g. first car will get number 1
+                cars.push(Car::new(track.get_start_pos(), i + 1, &config));
+            }
+        }
+
+        let mut drivers = vec![];
+        if human {
+            drivers.push(Car::new_human(track.get_start_pos()));
         }
+        for i in 0..config.scripted_drivers {
+            drivers.push(Car::with_controller(
+                track.get_start_pos(),
+                size + i + 1,
+                Box::new(ScriptedController),
+                SCRIPTED_COLOUR,
+            ));
+        }
+        if config.baseline_driver {
+            drivers.push(Car::with_controller(
+                track.get_start_pos(),
+                BASELINE_CAR_NUMBER,
+                Box::new(PursuitController::new(
+                    config.pursuit_gains,
+                    config.vehicle_model,
+                )),
+                BASELINE_COLOUR,
+            ));
+        }
+
+        // carry on racing the best lap from last time if there is one
+        let ghost = Ghost::load_for(&track, &config).ok();
+        let replay_lap = Recording::load_for(&track, &config)
+            .ok()
+            .map(|recording| recording.fastest_lap);
+        let optimiser = new_optimiser(&config);
+        let brain_fitness = resumed.as_ref().map(|brain| brain.fitness);
 
         Self {
-            generation: 0,
+            generation,
             cars,
+            drivers,
             track,
             ticks: 0,
             time_limit,
             timer: Timer::new(),
             data_file: File::create("fitness_values_test1.csv").unwrap(),
+            config,
+            ghost,
+            replay_lap,
+            brain_fitness,
+            optimiser,
+            behaviours: vec![vec![]; size],
+            archive,
         }
     }
 
     pub fn draw(&self) {
         self.track.draw();
 
+        // the ghost goes underneath everything else
+        if let Some(ghost) = &self.ghost {
+            // it races the person's current lap, or the generation from the start line
+            let mut tick = self.ticks as usize;
+            if let Some(human) = self.drivers.iter().find(|d| d.number == HUMAN_CAR_NUMBER) {
+                tick = human.get_lap_timer();
+            }
+            ghost.draw(tick);
+        }
+
         // find best performer
-        let mut best_fitness = -1000000; // close enough to - infinity
+        let mut best_fitness = f64::MIN;
         let mut best_car_number: usize = 0;
         for i in 0..self.cars.len() {
             let car = &self.cars[i];
-            if car.fitness > best_fitness {
-                best_fitness = car.fitness;
+            let fitness = car.get_final_fitness(&self.track, self.ticks + 1, &self.config.fitness);
+            if fitness > best_fitness {
+                best_fitness = fitness;
                 best_car_number = car.number;
             }
         }
@@ -62,6 +183,24 @@ impl Population {
             }
         }
 
+        // the other drivers go on top of the ai cars
+        for driver in self.drivers.iter() {
+            driver.draw(false);
+
+            // lap info for the person driving
+            if driver.number == HUMAN_CAR_NUMBER {
+                let text = format!("Lap: {}  Laps: {}", driver.get_lap_timer(), driver.laps);
+                draw_text(&text, 10.0, 30.0, 35.0, BLACK);
+                let text = format!("Best: {}", self.timer.get_fastest_time().2);
+                draw_text(&text, 10.0, 65.0, 35.0, BLACK);
+            }
+        }
+
+        // nothing to evolve when driving alone
+        if self.cars.is_empty() {
+            return;
+        }
+
         // draw the generation number
         let text = format!("Generation: {}", self.generation);
         let colour = color_u8!(20, 20, 20, 100);
@@ -74,44 +213,209 @@ impl Population {
     pub fn update(&mut self) {
         let dt = get_frame_time();
 
-        if self.ticks >= self.time_limit || self.all_cars_crashed() {
+        if !self.cars.is_empty() && (self.ticks >= self.time_limit || self.all_cars_crashed()) {
             self.new_population();
         }
 
-        for car in self.cars.iter_mut() {
-            car.update(&self.track, dt);
-            if !car.is_on_track(&self.track) {
-                car.crashed();
+        // where every car is before anyone moves, so the order they update in doesn't matter
+        let mut traffic = vec![];
+        if self.config.cars_in_rays {
+            for car in self.cars.iter().chain(self.drivers.iter()) {
+                if car.collidable && !car.crashed {
+                    traffic.push(car.get_outline());
+                }
             }
+        }
+
+        for car in self.cars.iter_mut().chain(self.drivers.iter_mut()) {
+            car.step(&self.track, dt, &self.config, &traffic);
 
             if car.just_lapped {
-                self.timer
-                    .enter_time((car.number, self.generation, car.lap_time as f32));
+                let place =
+                    self.timer
+                        .enter_time((car.number, self.generation, car.lap_time as f32));
                 print!("{esc}c", esc = 27 as char);
                 self.timer.print_times();
+
+                // keep a replay of the run with the fastest lap, from this run or an earlier one
+                // a replay only has the one car in it so laps with collisions can't be replayed
+                let faster = self.replay_lap.is_none_or(|lap| car.lap_time < lap);
+                if place == 0 && faster && !self.config.car_collisions {
+                    let recording =
+                        Recording::from_car(car, &self.track, &self.config, self.generation);
+                    match recording.save() {
+                        Ok(()) => self.replay_lap = Some(car.lap_time),
+                        Err(e) => println!("Could not save replay - {}", e),
+                    }
+                }
+
+                // and a ghost of it if it beats every lap driven on this track with these settings
+                let beaten = match &self.ghost {
+                    Some(ghost) => car.lap_time < ghost.lap_time,
+                    None => true,
+                };
+                if beaten && !car.get_last_lap().is_empty() {
+                    let ghost = Ghost::from_car(car, &self.track, &self.config, self.generation);
+                    if let Err(e) = ghost.save() {
+                        println!("Could not save ghost - {}", e);
+                    }
+                    self.ghost = Some(ghost);
+                }
             }
         }
 
+        if self.config.car_collisions || self.config.cars_in_rays {
+            let mut cars: Vec<&mut Car> = self
+                .cars
+                .iter_mut()
+                .chain(self.drivers.iter_mut())
+                .collect();
+            resolve_collisions(&mut cars, self.config.car_collisions);
+        }
+
+        for driver in self.drivers.iter_mut() {
+            // back to the start line to have another go
+            if driver.crashed {
+                driver.reset(self.track.get_start_pos());
+            }
+        }
+
+        if self.config.novelty_search {
+            self.sample_behaviours();
+        }
+
         self.ticks += 1;
     }
 
+    fn sample_behaviours(&mut self) {
+        // where the cars are at even points through the generation
+        let samples = self.config.novelty_samples.max(1);
+        let interval = (self.time_limit as usize / samples).max(1);
+        if !(self.ticks as usize + 1).is_multiple_of(interval) {
+            return;
+        }
+        for (car, behaviour) in self.cars.iter().zip(self.behaviours.iter_mut()) {
+            if behaviour.len() < samples * 2 {
+                let centre = car.get_centre();
+                behaviour.push(centre.x as f64 / WINDOW_WIDTH as f64);
+                behaviour.push(centre.y as f64 / WINDOW_HEIGHT as f64);
+            }
+        }
+    }
+
+    fn score_novelty(&mut self, evaluations: &mut [Evaluation]) {
+        // cars that stopped early stay where they finished for the rest of the samples
+        let samples = self.config.novelty_samples.max(1);
+        for (car, behaviour) in self.cars.iter().zip(self.behaviours.iter_mut()) {
+            let centre = car.get_centre();
+            while behaviour.len() < samples * 2 {
+                behaviour.push(centre.x as f64 / WINDOW_WIDTH as f64);
+                behaviour.push(centre.y as f64 / WINDOW_HEIGHT as f64);
+            }
+        }
+
+        let mut novelty = vec![];
+        for i in 0..self.behaviours.len() {
+            let neighbours = self.config.novelty_neighbours;
+            novelty.push(self.archive.novelty(i, &self.behaviours, neighbours));
+        }
+
+        // both spread from 0 to 1 over the generation so they can be blended
+        let weight = self.config.novelty_weight.clamp(0.0, 1.0);
+        let fitnesses: Vec<f64> = evaluations.iter().map(|e| e.fitness).collect();
+        let novelty_scaled = normalise(&novelty);
+        let fitness_scaled = normalise(&fitnesses);
+        for (i, evaluation) in evaluations.iter_mut().enumerate() {
+            evaluation.fitness = weight * novelty_scaled[i] + (1.0 - weight) * fitness_scaled[i];
+        }
+
+        // the most novel are remembered for the generations after
+        let mut order: Vec<usize> = (0..novelty.len()).collect();
+        order.sort_by(|&a, &b| novelty[b].total_cmp(&novelty[a]));
+        for &i in order.iter().take(self.config.novelty_archive_rate) {
+            self.archive.behaviours.push(self.behaviours[i].clone());
+        }
+
+        self.behaviours = vec![vec![]; self.cars.len()];
+    }
+
     fn new_population(&mut self) {
-        // reset all the cars to start position
+        // how every car did, in the order the optimiser made them
         let size = self.cars.len();
-        let mut cars: Vec<Car> = vec![];
+        let mut fitnesses: Vec<f64> = vec![];
+        let mut evaluations: Vec<Evaluation> = vec![];
+        let mut best = 0;
+        for (i, car) in self.cars.iter().enumerate() {
+            fitnesses.push(car.get_final_fitness(
+                &self.track,
+                self.ticks + 1,
+                &self.config.fitness,
+            ));
+            if fitnesses[i] > fitnesses[best] {
+                best = i;
+            }
+            // the evolving cars are always driven by networks
+            evaluations.push(Evaluation {
+                network: car.brain().unwrap().clone(),
+                fitness: fitnesses[i],
+                objectives: car.get_objectives(&self.track, self.time_limit),
+            });
+        }
 
-        // 100% of children made by top 2 performers
-        self.cars.sort_by(|a, b| {
-            b.get_final_fitness(self.ticks + 1)
-                .cmp(&a.get_final_fitness(self.ticks + 1))
-        });
+        // the trade-offs between the objectives for this generation
+        if self.config.optimiser == OptimiserKind::Nsga2 {
+            let objectives: Vec<_> = evaluations.iter().map(|e| e.objectives).collect();
+            if let Err(e) = save_front(self.generation, &objectives) {
+                println!("Could not save pareto front - {}", e);
+            }
+        }
+
+        // novelty search scores the cars by how differently they drove instead
+        if self.config.novelty_search {
+            self.score_novelty(&mut evaluations);
+        }
+
+        // the optimiser makes the next generation from them
+        self.optimiser.tell(&evaluations);
+        let mut cars: Vec<Car> = vec![];
+        for (i, network) in self.optimiser.ask(size).into_iter().enumerate() {
+            cars.push(Car::with_brain(
+                self.track.get_start_pos(),
+                i + 1,
+                network,
+                &self.config,
+            ));
+        }
 
-        for i in 0..(size) {
-            cars.push(self.reproduce(&self.cars[0], &self.cars[1], i));
+        // keep the best brain of the run so it can enter races, a worse generation doesn't replace it
+        let name = format!("{}_{}", self.track.name, self.config.seed);
+        if self
+            .brain_fitness
+            .is_none_or(|fitness| fitnesses[best] > fitness)
+        {
+            let brain = SavedBrain {
+                name: name.clone(),
+                track_name: self.track.name.clone(),
+                generation: self.generation,
+                fitness: fitnesses[best],
+                settings: self.config.describe(),
+                network: evaluations[best].network.clone(),
+            };
+            match brain.save() {
+                Ok(()) => self.brain_fitness = Some(brain.fitness),
+                Err(e) => println!("Could not save brain - {}", e),
+            }
+        }
+        // the archive is saved every generation so the run can carry on from where it got to
+        if self.config.novelty_search {
+            if let Err(e) = self.archive.save(&NoveltyArchive::path_for(&name)) {
+                println!("Could not save novelty archive - {}", e);
+            }
         }
 
+        // the optimiser gets the exact score, the csv and log keep whole numbers
+        let best_fitness = fitnesses[best] as i32;
         // add data to csv file
-        let best_fitness = self.cars[0].get_final_fitness(self.ticks + 1);
         writeln!(self.data_file, "{},{}", self.generation, best_fitness).unwrap();
 
         println!(
@@ -135,64 +439,6 @@ impl Population {
         return true;
     }
 
-    fn reproduce(&self, car1: &Car, car2: &Car, i: usize) -> Car {
-        let mut child_car = Car::new(self.track.get_start_pos(), i + 1);
-        let mut child_net = car1.brain.clone();
-        let network2 = &car2.brain;
-
-        // apply cross over
-        for i in 0..child_net.layers.len() {
-            let layer2 = &network2.layers[i];
-            let biases2 = &layer2.bias;
-            let weights2 = &layer2.weights;
-
-            let weights_size = weights2.len() * weights2[0].len();
-            let biases_size = biases2.len();
-
-            let weights_crossover = gen_range(0, weights_size - 1);
-            let biases_crossover = gen_range(0, biases_size - 1);
-
-            let child_layer = &mut child_net.layers[i];
-
-            // cross over the weights
-            for j in 0..=weights_crossover {
-                let new_weight = weights2[j / weights2[0].len()][j % weights2[0].len()];
-                child_layer.weights[j / weights2[0].len()][j % weights2[0].len()] = new_weight;
-            }
-
-            // cross over the biases
-            for k in 0..=biases_crossover {
-                let new_bias = biases2[k];
-                child_layer.bias[k] = new_bias;
-            }
-
-            // apply mutations
-            for row in child_layer.weights.iter_mut() {
-                for weight in row.iter_mut() {
-                    if gen_range(0.0, 1.0) <= 0.02 {
-                        *weight = gen_range(-1.0, 1.0);
-                    }
-                    if gen_range(0.0, 1.0) <= 0.03 {
-                        *weight += gen_range(-0.5, 0.5);
-                    }
-                }
-            }
-
-            for bias in child_layer.bias.iter_mut() {
-                if (gen_range(0.0, 1.0)) <= 0.02 {
-                    *bias = gen_range(-0.5, 0.5);
-                }
-                if (gen_range(0.0, 1.0) <= 0.03) {
-                    *bias += gen_range(-0.5, 0.5);
-                }
-            }
-        }
-
-        child_car.brain = child_net;
-
-        return child_car;
-    }
-
     pub fn draw_timer_bar(&self) {
         // set the height of the bar
         let height = 30.0;
@@ -219,3 +465,13 @@ impl Population {
         );
     }
 }
+
+fn normalise(values: &[f64]) -> Vec<f64> {
+    // spread from 0 for the lowest to 1 for the highest
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if max <= min {
+        return vec![0.0; values.len()];
+    }
+    return values.iter().map(|v| (v - min) / (max - min)).collect();
+}
@@ -0,0 +1,375 @@
+use crate::brain::SavedBrain;
+use crate::car::*;
+use crate::config::RunConfig;
+use crate::controller::{NetworkController, PursuitController, ScriptedController};
+use crate::track::Track;
+use crate::utils::repeat_space;
+use macroquad::prelude::*;
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+
+pub const RESULTS_FILE: &str = "race_results.txt";
+
+// so the brains can be told apart on track
+const ENTRANT_COLOURS: [Color; 8] = [
+    color_u8!(255, 80, 80, 255),
+    color_u8!(80, 140, 255, 255),
+    color_u8!(255, 220, 60, 255),
+    color_u8!(90, 220, 120, 255),
+    color_u8!(255, 150, 60, 255),
+    color_u8!(200, 110, 255, 255),
+    color_u8!(60, 220, 220, 255),
+    color_u8!(255, 120, 200, 255),
+];
+const PANEL_COLOUR: Color = color_u8!(255, 255, 255, 220);
+
+struct Entrant {
+    name: String,
+    car: Car,
+    finish_tick: Option<u32>, // when they crossed the line for the last time
+    best_lap: Option<usize>,
+    laps: usize,    // times over the start line, the grid is behind it
+    lap_start: u32, // tick the current lap started on
+}
+
+impl Entrant {
+    fn new(name: String, car: Car) -> Self {
+        Self {
+            name,
+            car,
+            finish_tick: None,
+            best_lap: None,
+            laps: 0,
+            lap_start: 0,
+        }
+    }
+
+    fn laps_done(&self) -> usize {
+        return self.laps;
+    }
+
+    fn count_laps(&mut self, track: &Track, tick: u32) {
+        // a lap each time the car gets another track length past the start line
+        // going back over the line and across it again doesn't count twice
+        let laps = (self.car.get_progress(track) / track.get_total_length()).floor();
+        if laps < 1.0 || laps as usize <= self.laps {
+            return;
+        }
+        let lap_time = (tick - self.lap_start) as usize;
+        self.best_lap = Some(self.best_lap.map_or(lap_time, |b| b.min(lap_time)));
+        self.laps = laps as usize;
+        self.lap_start = tick;
+    }
+
+    fn status(&self) -> &str {
+        if self.finish_tick.is_some() {
+            return "Finished";
+        }
+        if self.car.crashed {
+            return "DNF";
+        }
+        return "Running";
+    }
+}
+
+// every car starts from the grid and races for a set number of laps
+pub struct Race {
+    track: Track,
+    config: RunConfig,
+    entrants: Vec<Entrant>,
+    ticks: u32,
+    over: bool,
+}
+
+impl Race {
+    pub fn new(track: Track, config: RunConfig) -> Result<Self, String> {
+        let mut names = config.race_brains.clone();
+        if names.is_empty() {
+            names = SavedBrain::available();
+        }
+
+        // saved brains first, in the order they were asked for
+        let mut entrants = vec![];
+        for (i, name) in names.iter().enumerate() {
+            let brain = SavedBrain::by_name(name)?;
+            let sensors = brain.sensors();
+            let controller =
+                NetworkController::new(brain.network).with_latency(config.actuator_latency);
+            let colour = ENTRANT_COLOURS[i % ENTRANT_COLOURS.len()];
+            let car = Car::with_controller(Vec2::ZERO, i + 1, Box::new(controller), colour);
+            entrants.push(Entrant::new(name.clone(), car.with_sensors(sensors)));
+        }
+
+        // then anyone else from the config
+        let mut others = vec![];
+        for i in 0..config.scripted_drivers {
+            let car = Car::with_controller(
+                Vec2::ZERO,
+                names.len() + i + 1,
+                Box::new(ScriptedController),
+                WHITE,
+            );
+            others.push((format!("Scripted {}", i + 1), car));
+        }
+        if config.baseline_driver {
+            let controller = Box::new(PursuitController::new(
+                config.pursuit_gains,
+                config.vehicle_model,
+            ));
+            let car = Car::with_controller(Vec2::ZERO, BASELINE_CAR_NUMBER, controller, WHITE);
+            others.push(("Baseline".to_string(), car));
+        }
+        if config.race_human {
+            others.push(("You".to_string(), Car::new_human(Vec2::ZERO)));
+        }
+        for (name, car) in others {
+            entrants.push(Entrant::new(name, car));
+        }
+
+        if entrants.is_empty() {
+            return Err("nobody to race, train and save some brains first".to_string());
+        }
+
+        // line everyone up on the grid
+        let slots = track.get_grid_slots(entrants.len());
+        for (entrant, (centre, angle)) in entrants.iter_mut().zip(slots) {
+            entrant.car.place_on_grid(&track, centre, angle);
+        }
+
+        println!(
+            "Race - {} laps of {} with {} cars",
+            config.race_laps,
+            track.name,
+            entrants.len()
+        );
+
+        return Ok(Self {
+            track,
+            config,
+            entrants,
+            ticks: 0,
+            over: false,
+        });
+    }
+
+    pub fn update(&mut self) {
+        if self.over {
+            return;
+        }
+        let dt = get_frame_time();
+
+        // where every car is before anyone moves
+        let mut traffic = vec![];
+        if self.config.cars_in_rays {
+            for entrant in self.entrants.iter() {
+                if entrant.car.collidable && !entrant.car.crashed && entrant.finish_tick.is_none() {
+                    traffic.push(entrant.car.get_outline());
+                }
+            }
+        }
+
+        for entrant in self.entrants.iter_mut() {
+            // cars that have finished are parked
+            if entrant.finish_tick.is_some() || entrant.car.crashed {
+                continue;
+            }
+            entrant.car.step(&self.track, dt, &self.config, &traffic);
+
+            // the first lap is timed from the start of the race
+            entrant.count_laps(&self.track, self.ticks + 1);
+            if entrant.laps_done() >= self.config.race_laps {
+                entrant.finish_tick = Some(self.ticks);
+            }
+        }
+
+        if self.config.car_collisions || self.config.cars_in_rays {
+            let mut cars: Vec<&mut Car> = self
+                .entrants
+                .iter_mut()
+                .filter(|e| e.finish_tick.is_none())
+                .map(|e| &mut e.car)
+                .collect();
+            resolve_collisions(&mut cars, self.config.car_collisions);
+        }
+
+        self.ticks += 1;
+
+        // over when nobody is left running, or it's gone on too long
+        let running = self.entrants.iter().any(|e| e.status() == "Running");
+        if !running || self.ticks >= self.config.race_tick_limit {
+            self.over = true;
+            self.print_results();
+            if let Err(e) = self.save_results() {
+                println!("Could not save race results - {}", e);
+            }
+        }
+    }
+
+    fn standings(&self) -> Vec<&Entrant> {
+        // finishers in the order they finished then everyone else by how far they got
+        let mut standings: Vec<&Entrant> = self.entrants.iter().collect();
+        standings.sort_by(|a, b| match (a.finish_tick, b.finish_tick) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => {
+                let progress_a = a.car.get_progress(&self.track);
+                let progress_b = b.car.get_progress(&self.track);
+                progress_b.total_cmp(&progress_a)
+            }
+        });
+        return standings;
+    }
+
+    fn results(&self) -> Vec<[String; 6]> {
+        // position, name, status, laps, race time, best lap
+        let mut rows = vec![];
+        for (i, entrant) in self.standings().iter().enumerate() {
+            let time = match entrant.finish_tick {
+                Some(tick) => tick.to_string(),
+                None => "-".to_string(),
+            };
+            let best = match entrant.best_lap {
+                Some(lap) => lap.to_string(),
+                None => "-".to_string(),
+            };
+            rows.push([
+                (i + 1).to_string(),
+                entrant.name.clone(),
+                entrant.status().to_string(),
+                entrant.laps_done().min(self.config.race_laps).to_string(),
+                time,
+                best,
+            ]);
+        }
+        return rows;
+    }
+
+    fn print_results(&self) {
+        // same layout as the lap times, the name column is wider for brain names
+        let widths = [5, 24, 10, 6, 10, 10];
+        let headers = ["Pos", "Driver", "Status", "Laps", "Time", "Best Lap"];
+
+        println!(
+            "Race results - {} laps of {}",
+            self.config.race_laps, self.track.name
+        );
+        for (header, width) in headers.iter().zip(widths) {
+            print!("|{}", header);
+            repeat_space(width - header.len());
+        }
+        println!("|");
+        for row in self.results() {
+            for (value, width) in row.iter().zip(widths) {
+                print!("|{}", value);
+                repeat_space(width.saturating_sub(value.len()));
+            }
+            println!("|");
+        }
+    }
+
+    fn save_results(&self) -> std::io::Result<()> {
+        // every race is added to the end of the results file as csv
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(RESULTS_FILE)?;
+        writeln!(
+            file,
+            "# {} laps of {}, seed {}",
+            self.config.race_laps, self.track.name, self.config.seed
+        )?;
+        writeln!(file, "position,driver,status,laps,time,best_lap")?;
+        for row in self.results() {
+            writeln!(file, "{}", row.join(","))?;
+        }
+        return Ok(());
+    }
+
+    pub fn draw(&self) {
+        self.track.draw();
+
+        for entrant in self.entrants.iter() {
+            entrant.car.draw(false);
+            let centre = entrant.car.get_centre();
+            draw_text(&entrant.name, centre.x + 20.0, centre.y - 20.0, 20.0, BLACK);
+        }
+
+        if self.over {
+            self.draw_results();
+            return;
+        }
+
+        // live standings down the left
+        let leader_laps = self
+            .entrants
+            .iter()
+            .map(|e| e.laps_done())
+            .max()
+            .unwrap_or(0);
+        let lap = (leader_laps + 1).min(self.config.race_laps);
+        let text = format!("Lap {} / {}", lap, self.config.race_laps);
+        draw_text(&text, 10.0, 30.0, 35.0, BLACK);
+        for (i, entrant) in self.standings().iter().enumerate() {
+            let text = format!("{}. {}", i + 1, entrant.name);
+            draw_text(&text, 10.0, 60.0 + i as f32 * 22.0, 22.0, BLACK);
+        }
+    }
+
+    fn draw_results(&self) {
+        let rows = self.results();
+        let columns = [0.0, 60.0, 360.0, 490.0, 560.0, 660.0];
+        let headers = ["Pos", "Driver", "Status", "Laps", "Time", "Best"];
+        let (x, y) = (200.0, 150.0);
+
+        let height = 90.0 + rows.len() as f32 * 30.0;
+        draw_rectangle(x - 20.0, y - 50.0, 800.0, height, PANEL_COLOUR);
+        draw_text("Results", x, y - 15.0, 40.0, BLACK);
+
+        for (header, column) in headers.iter().zip(columns) {
+            draw_text(header, x + column, y + 20.0, 26.0, BLACK);
+        }
+        for (i, row) in rows.iter().enumerate() {
+            let row_y = y + 50.0 + i as f32 * 30.0;
+            for (value, column) in row.iter().zip(columns) {
+                draw_text(value, x + column, row_y, 24.0, BLACK);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::PursuitGains;
+    use crate::track::DEFAULT_TRACK_NAME;
+
+    #[test]
+    fn laps_are_counted_from_the_start_line() {
+        let config = RunConfig::default();
+        let track = Track::by_name(DEFAULT_TRACK_NAME).unwrap();
+        let total = track.get_total_length();
+
+        // the second slot is behind the first control point, where the sectors start
+        for (centre, angle) in track.get_grid_slots(2) {
+            let controller = PursuitController::new(PursuitGains::default(), config.vehicle_model);
+            let car = Car::with_controller(centre, 1, Box::new(controller), WHITE);
+            let mut entrant = Entrant::new("Baseline".to_string(), car);
+            entrant.car.place_on_grid(&track, centre, angle);
+            assert!(entrant.car.get_progress(&track) < 0.0);
+
+            let mut tick = 0;
+            while entrant.laps_done() == 0 && !entrant.car.crashed && tick < 5000 {
+                let before = entrant.car.get_progress(&track);
+                entrant.car.step(&track, 1.0 / 60.0, &config, &[]);
+                tick += 1;
+                entrant.count_laps(&track, tick);
+                // only done once it's been all the way round from the line
+                let after = entrant.car.get_progress(&track);
+                assert_eq!(entrant.laps_done() == 1, before < total && after >= total);
+            }
+            assert_eq!(entrant.laps_done(), 1, "crashed: {}", entrant.car.crashed);
+            assert_eq!(entrant.best_lap, Some(tick as usize));
+        }
+    }
+}
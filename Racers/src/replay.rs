@@ -0,0 +1,381 @@
+use crate::car::Car;
+use crate::config::RunConfig;
+use crate::controller::{Controls, ReplayController};
+use crate::track::Track;
+use crate::ui::Slider;
+use crate::WINDOW_HEIGHT;
+use macroquad::prelude::*;
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+
+pub const REPLAYS_FOLDER: &str = "replays";
+const REPLAY_COLOUR: Color = color_u8!(255, 215, 0, 255);
+const PLAYBACK_SPEEDS: [f32; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+
+// everything needed to drive a run again exactly as it happened
+// it's the car's whole run from the start line, ending with its fastest lap
+pub struct Recording {
+    pub track_name: String,
+    pub smoothing: usize,
+    pub vehicle_model: String,
+    pub car_number: usize,
+    pub driver: String, // the controller that drove the car
+    pub generation: usize,
+    pub fastest_lap: usize, // the lap the run ends with, in ticks
+    pub start_position: Vec2,
+    pub start_angle: f32,
+    pub settings: Vec<(String, String)>, // the run config, includes the seed
+    pub ticks: Vec<(f32, Controls)>,     // frame time and controls for every tick
+}
+
+impl Recording {
+    pub fn from_car(car: &Car, track: &Track, config: &RunConfig, generation: usize) -> Self {
+        let (start_position, start_angle) = car.get_start_state();
+        return Self {
+            track_name: track.name.clone(),
+            smoothing: track.get_resolution(),
+            vehicle_model: config.vehicle_model.name().to_string(),
+            car_number: car.number,
+            driver: car.driver_name().to_string(),
+            generation,
+            fastest_lap: car.lap_time,
+            start_position,
+            start_angle,
+            settings: config.describe(),
+            ticks: car.get_recording().to_vec(),
+        };
+    }
+
+    pub fn path_for(track_name: &str, smoothing: usize, vehicle_model: &str) -> String {
+        // lap times only compare with the same physics, like ghosts
+        return format!(
+            "{}/{}_{}_{}.replay",
+            REPLAYS_FOLDER, track_name, smoothing, vehicle_model
+        );
+    }
+
+    pub fn load_for(track: &Track, config: &RunConfig) -> Result<Self, String> {
+        // the replay driven with the same settings as this run
+        let smoothing = track.get_resolution();
+        let vehicle_model = config.vehicle_model.name();
+        let path = Self::path_for(&track.name, smoothing, vehicle_model);
+        let recording = Self::load(&path)?;
+        if recording.smoothing != smoothing || recording.vehicle_model != vehicle_model {
+            return Err(format!("{}: driven with different settings", path));
+        }
+        return Ok(recording);
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        // one replay per track and settings, only replaced by a run with a faster lap
+        fs::create_dir_all(REPLAYS_FOLDER)?;
+        return self.save_to(&Self::path_for(
+            &self.track_name,
+            self.smoothing,
+            &self.vehicle_model,
+        ));
+    }
+
+    pub fn save_to(&self, path: &str) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+
+        writeln!(file, "# racers replay")?;
+        writeln!(file, "track = {}", self.track_name)?;
+        writeln!(file, "smoothing = {}", self.smoothing)?;
+        writeln!(file, "vehicle_model = {}", self.vehicle_model)?;
+        writeln!(file, "car = {}", self.car_number)?;
+        writeln!(file, "driver = {}", self.driver)?;
+        writeln!(file, "generation = {}", self.generation)?;
+        writeln!(file, "fastest_lap = {}", self.fastest_lap)?;
+        writeln!(
+            file,
+            "start = {}, {}, {}",
+            self.start_position.x, self.start_position.y, self.start_angle
+        )?;
+        for (key, value) in self.settings.iter() {
+            writeln!(file, "setting {} = {}", key, value)?;
+        }
+
+        // then one line per tick: dt, throttle, steer, brake
+        writeln!(file, "ticks")?;
+        for (dt, controls) in self.ticks.iter() {
+            writeln!(
+                file,
+                "{}, {}, {}, {}",
+                dt, controls.throttle, controls.steer, controls.brake
+            )?;
+        }
+
+        return Ok(());
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+        let mut recording = Self {
+            track_name: String::new(),
+            smoothing: 1,
+            vehicle_model: String::new(),
+            car_number: 0,
+            driver: String::new(),
+            generation: 0,
+            fastest_lap: 0,
+            start_position: Vec2::ZERO,
+            start_angle: 0.0,
+            settings: vec![],
+            ticks: vec![],
+        };
+
+        let mut in_ticks = false;
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("{}:{}: {}", path, line_number + 1, message);
+            let numbers = |text: &str| -> Result<Vec<f32>, String> {
+                return text
+                    .split(',')
+                    .map(|v| v.trim().parse::<f32>().map_err(|e| error(e.to_string())))
+                    .collect();
+            };
+
+            if in_ticks {
+                let values = numbers(line)?;
+                if values.len() != 4 {
+                    return Err(error("expected 'dt, throttle, steer, brake'".to_string()));
+                }
+                let controls = Controls {
+                    throttle: values[1],
+                    steer: values[2],
+                    brake: values[3],
+                };
+                recording.ticks.push((values[0], controls));
+                continue;
+            }
+
+            if line == "ticks" {
+                in_ticks = true;
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("unexpected line '{}'", line)));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let parse_error = |e: std::num::ParseIntError| error(e.to_string());
+
+            if let Some(setting) = key.strip_prefix("setting ") {
+                recording
+                    .settings
+                    .push((setting.trim().to_string(), value.to_string()));
+                continue;
+            }
+
+            match key {
+                "track" => recording.track_name = value.to_string(),
+                "smoothing" => recording.smoothing = value.parse().map_err(parse_error)?,
+                "vehicle_model" => recording.vehicle_model = value.to_string(),
+                "car" => recording.car_number = value.parse().map_err(parse_error)?,
+                "driver" => recording.driver = value.to_string(),
+                "generation" => recording.generation = value.parse().map_err(parse_error)?,
+                // older replays called it lap_time
+                "fastest_lap" | "lap_time" => {
+                    recording.fastest_lap = value.parse().map_err(parse_error)?
+                }
+                "start" => {
+                    let values = numbers(value)?;
+                    if values.len() != 3 {
+                        return Err(error("expected 'start = x, y, angle'".to_string()));
+                    }
+                    recording.start_position = vec2(values[0], values[1]);
+                    recording.start_angle = values[2];
+                }
+                _ => return Err(error(format!("unknown key '{}'", key))),
+            }
+        }
+
+        return Ok(recording);
+    }
+}
+
+// the replay screen, drives a car through a recording again
+pub struct ReplayViewer {
+    recording: Recording,
+    track: Track,
+    config: RunConfig,
+    car: Car,
+
+    tick: usize,
+    paused: bool,
+    speed_index: usize, // index into PLAYBACK_SPEEDS
+    tick_progress: f32, // part ticks built up at slow speeds
+    scrubber: Slider,
+}
+
+impl ReplayViewer {
+    pub fn new(recording: Recording) -> Result<Self, String> {
+        let track = Track::by_name(&recording.track_name)?.smoothed(recording.smoothing);
+
+        // the physics needs the same settings as the original run
+        let mut config = RunConfig::default();
+        for (key, value) in recording.settings.iter() {
+            config.set(key, value);
+        }
+
+        let controls: Vec<Controls> = recording.ticks.iter().map(|(_, c)| *c).collect();
+        let controller = ReplayController::new(controls);
+        let mut car = Car::with_controller(
+            recording.start_position,
+            recording.car_number,
+            Box::new(controller),
+            REPLAY_COLOUR,
+        )
+        .with_sensors(config.sensors);
+        car.set_angle(recording.start_angle);
+
+        let last_tick = recording.ticks.len() as i32;
+        return Ok(Self {
+            recording,
+            track,
+            config,
+            car,
+            tick: 0,
+            paused: false,
+            speed_index: 2, // normal speed
+            tick_progress: 0.0,
+            scrubber: Slider::new(100.0, WINDOW_HEIGHT as f32 - 60.0, 0, last_tick, 0),
+        });
+    }
+
+    fn step(&mut self) {
+        // drive one recorded tick with the frame time it was recorded with
+        if let Some((dt, _)) = self.recording.ticks.get(self.tick) {
+            self.car.step(&self.track, *dt, &self.config, &[]);
+            self.tick += 1;
+        }
+    }
+
+    fn seek(&mut self, tick: usize) {
+        // start again and drive up to the tick, the physics is deterministic
+        // so this ends up in exactly the same place as playing through
+        self.car.reset(self.recording.start_position);
+        self.car.set_angle(self.recording.start_angle);
+        self.tick = 0;
+        while self.tick < tick.min(self.recording.ticks.len()) {
+            self.step();
+        }
+    }
+
+    pub fn update(&mut self) {
+        // space pauses, up and down change the speed
+        if is_key_pressed(KeyCode::Space) {
+            self.paused = !self.paused;
+        }
+        if is_key_pressed(KeyCode::Up) {
+            self.speed_index = (self.speed_index + 1).min(PLAYBACK_SPEEDS.len() - 1);
+        }
+        if is_key_pressed(KeyCode::Down) {
+            self.speed_index = self.speed_index.saturating_sub(1);
+        }
+
+        // dragging the slider scrubs through the run
+        self.scrubber.update();
+        if self.scrubber.selected {
+            let target = self.scrubber.value as usize;
+            if target != self.tick {
+                self.seek(target);
+            }
+            return;
+        }
+
+        if !self.paused {
+            self.tick_progress += PLAYBACK_SPEEDS[self.speed_index];
+            while self.tick_progress >= 1.0 {
+                self.step();
+                self.tick_progress -= 1.0;
+            }
+        }
+        self.scrubber.value = self.tick as i32;
+    }
+
+    pub fn draw(&self) {
+        self.track.draw();
+        self.car.draw(false);
+
+        let r = &self.recording;
+        let text = format!(
+            "Car {} ({}) - generation {} - whole run, ending with a {} tick lap",
+            r.car_number, r.driver, r.generation, r.fastest_lap
+        );
+        draw_text(&text, 10.0, 30.0, 30.0, BLACK);
+
+        let mut status = format!("x{}", PLAYBACK_SPEEDS[self.speed_index]);
+        if self.paused {
+            status = "Paused".to_string();
+        }
+        let text = format!(
+            "Tick {} / {}  {}  (space - pause, up/down - speed)",
+            self.tick,
+            r.ticks.len(),
+            status
+        );
+        draw_text(&text, 10.0, 60.0, 25.0, BLACK);
+
+        self.scrubber.draw();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::{PursuitController, PursuitGains};
+    use crate::track::DEFAULT_TRACK_NAME;
+
+    #[test]
+    fn replaying_a_saved_run_ends_in_the_same_place() {
+        // the baseline driver does a lap with an uneven frame time
+        let config = RunConfig::default();
+        let track = Track::by_name(DEFAULT_TRACK_NAME).unwrap();
+        let controller = PursuitController::new(PursuitGains::default(), config.vehicle_model);
+        let mut car = Car::with_controller(track.get_start_pos(), 1, Box::new(controller), WHITE)
+            .with_sensors(config.sensors);
+        let mut tick = 0;
+        while car.laps == 0 && !car.crashed && tick < 5000 {
+            let dt = [1.0 / 60.0, 1.0 / 50.0, 1.0 / 70.0][tick % 3];
+            car.step(&track, dt, &config, &[]);
+            tick += 1;
+        }
+        assert_eq!(car.laps, 1, "crashed: {}", car.crashed);
+
+        let path = std::env::temp_dir().join("racers_test.replay");
+        let path = path.to_str().unwrap();
+        Recording::from_car(&car, &track, &config, 3)
+            .save_to(path)
+            .unwrap();
+        let recording = Recording::load(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(recording.fastest_lap, car.lap_time);
+        assert_eq!(recording.ticks.len(), tick);
+
+        // driven again from the file it ends up exactly where it did
+        let mut viewer = ReplayViewer::new(recording).unwrap();
+        viewer.seek(tick);
+        assert_eq!(viewer.car.get_centre(), car.get_centre());
+        assert_eq!(viewer.car.lap_time, car.lap_time);
+        assert_eq!(viewer.car.laps, 1);
+
+        // and scrubbing back and forward again does too
+        viewer.seek(tick / 2);
+        viewer.seek(tick);
+        assert_eq!(viewer.car.get_centre(), car.get_centre());
+    }
+
+    #[test]
+    fn replays_are_kept_per_smoothing_and_vehicle_model() {
+        let arcade = Recording::path_for("oval", 1, "arcade");
+        assert_ne!(arcade, Recording::path_for("oval", 8, "arcade"));
+        assert_ne!(arcade, Recording::path_for("oval", 1, "bicycle"));
+    }
+}
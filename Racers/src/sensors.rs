@@ -0,0 +1,67 @@
+use crate::WINDOW_WIDTH;
+
+// what a car can sense, the network's input size follows from this
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SensorConfig {
+    pub rays: usize,      // spread across the field of view in front
+    pub fov: f32,         // degrees
+    pub range: f32,       // how far the rays reach (px), distances are normalised against it
+    pub rear_rays: usize, // spread across the rest of the circle behind the car
+
+    // the scalar inputs after the rays
+    pub speed: bool,          // 1 value, speed / MAX_SPEED
+    pub velocity: bool,       // 2 values, screen x and y
+    pub local_velocity: bool, // 2 values, forwards and sideways relative to the car
+    pub acceleration: bool,   // 2 values, screen x and y
+    pub steer: bool,          // 1 value
+    pub heading: bool,        // 1 value, sin of the angle
+
+    // where the track goes, relative to the car
+    pub lookahead_points: usize, // how much the centreline turns at each point ahead
+    pub lookahead_spacing: f32,  // distance between the points (px)
+    pub track_offset: bool,      // 1 value, distance from the centreline over half the width
+    pub heading_error: bool,     // 1 value, angle between the car and the centreline
+}
+
+impl Default for SensorConfig {
+    fn default() -> Self {
+        // the original sensors, 15 rays and 6 scalars
+        Self {
+            rays: 15,
+            fov: 200.0,
+            range: WINDOW_WIDTH as f32,
+            rear_rays: 0,
+            speed: false,
+            velocity: true,
+            local_velocity: false,
+            acceleration: true,
+            steer: true,
+            heading: true,
+            lookahead_points: 0,
+            lookahead_spacing: 100.0,
+            track_offset: false,
+            heading_error: false,
+        }
+    }
+}
+
+impl SensorConfig {
+    pub fn input_count(&self) -> usize {
+        let mut count = self.rays + self.rear_rays;
+        count += self.speed as usize;
+        count += 2 * self.velocity as usize;
+        count += 2 * self.local_velocity as usize;
+        count += 2 * self.acceleration as usize;
+        count += self.steer as usize;
+        count += self.heading as usize;
+        count += self.lookahead_points;
+        count += self.track_offset as usize;
+        count += self.heading_error as usize;
+        return count;
+    }
+
+    pub fn rear_fov(&self) -> f32 {
+        // whatever the front rays don't cover
+        return (360.0 - self.fov).max(0.0);
+    }
+}
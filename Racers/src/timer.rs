@@ -1,3 +1,4 @@
+use crate::car::{BASELINE_CAR_NUMBER, HUMAN_CAR_NUMBER};
 use crate::utils::repeat_space;
 
 pub struct Timer {
@@ -28,9 +29,9 @@ impl Timer {
 
     pub fn enter_time(&mut self, time: LapTime) -> i32 {
         // loop through all times and place in order
-        for i in (0..self.times.len()) {
+        for i in 0..self.times.len() {
             let t = &self.times[i];
-            if (t.0 == 0 || (time.2 < t.2)) {
+            if t.0 == 0 || (time.2 < t.2) {
                 // shift all times down 1 after this
                 self.times[i..].rotate_right(1);
 
@@ -52,8 +53,13 @@ impl Timer {
 
         for i in 0..self.times.len() {
             let t = self.times[i];
-            print!("|{}", t.0);
-            repeat_space(10 - format!("{}", t.0).len());
+            let car = match t.0 {
+                HUMAN_CAR_NUMBER => "You".to_string(),
+                BASELINE_CAR_NUMBER => "Baseline".to_string(),
+                _ => format!("{}", t.0),
+            };
+            print!("|{}", car);
+            repeat_space(10 - car.len());
             print!("|{}", t.1);
             repeat_space(10 - format!("{}", t.1).len());
             print!("|{}", t.2);
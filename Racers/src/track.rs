@@ -1,10 +1,39 @@
-use crate::utils::draw_thick_line;
+use crate::car::Car;
+use crate::grid::SegmentGrid;
+use crate::utils::{
+    catmull_rom, closest_t_on_segment, distance_to_segment, draw_tapered_line, lerp,
+    polygons_overlap, ray_circle_intersection,
+};
 use macroquad::prelude::*;
+use std::fs;
 
-pub const tarmac_colour: Color = color_u8!(171, 170, 167, 255);
-pub const checkpoint_colour: Color = color_u8!(36, 255, 251, 150);
+pub const TARMAC_COLOUR: Color = color_u8!(171, 170, 167, 255);
+pub const CHECKPOINT_COLOUR: Color = color_u8!(36, 255, 251, 150);
+pub const KERB_COLOURS: [Color; 2] = [color_u8!(220, 40, 40, 255), WHITE];
+pub const GRAVEL_COLOUR: Color = color_u8!(214, 196, 150, 255);
+pub const OBSTACLE_COLOUR: Color = color_u8!(60, 60, 70, 255);
 
-pub const test_track1: [Vec2; 20] = [
+// kerbs run along the outside of both track edges
+pub const KERB_WIDTH: f32 = 8.0;
+const KERB_STRIPE_LENGTH: f32 = 16.0;
+
+pub const TRACKS_FOLDER: &str = "tracks";
+pub const TRACK_EXTENSION: &str = "track";
+pub const DEFAULT_TRACK_NAME: &str = "test_track1";
+
+// size of the cells used to speed up ray casting against the track edges
+const GRID_CELL_SIZE: f32 = 40.0;
+// how many segments either side of the last known one to search when projecting
+const PROJECTION_SEARCH_RANGE: usize = 3;
+// how far along the centreline a car well off the track can be moved in one go (px)
+// far more than a car drives in a tick, much less than the way round a hairpin
+const PROJECTION_MAX_JUMP: f32 = 100.0;
+
+// starting grid layout, distances in px along the centreline
+const GRID_FIRST_GAP: f32 = 50.0; // from the start line back to pole position
+const GRID_ROW_SPACING: f32 = 90.0; // between rows, every row has two cars
+
+pub const TEST_TRACK1: [Vec2; 20] = [
     vec2(507.0, 142.0),
     vec2(654.0, 140.0),
     vec2(782.0, 139.0),
@@ -28,74 +57,1012 @@ pub const test_track1: [Vec2; 20] = [
 ];
 
 pub struct Track {
-    points_set: [Vec2; 20],
-    track_width: f32,
+    pub name: String,
+    control_points: Vec<Vec2>, // the points the track was designed with
+    control_widths: Vec<f32>,  // the width of the track at each control point
+    points_set: Vec<Vec2>,     // the centreline actually used (smoothed or not)
+    resolution: usize,         // points per control segment when smoothed
+    widths: Vec<f32>,          // the width at each point of the centreline
+
+    // the track edges, worked out once from the centreline and widths
+    left_boundary: Vec<Vec2>,
+    right_boundary: Vec<Vec2>,
+    boundary_grid: SegmentGrid,
+
+    // distance along the centreline to the start of each segment
+    cumulative_lengths: Vec<f32>,
+    total_length: f32,
+    start_distance: f32, // where the start line is along the centreline
+
+    // gravel traps as (centre, radius)
+    gravel_traps: Vec<(Vec2, f32)>,
+
+    obstacles: Vec<Obstacle>,
+}
+
+// things on the track that the cars crash into
+#[derive(Clone, Copy, Debug)]
+pub enum Obstacle {
+    Circle { centre: Vec2, radius: f32 },
+    Rect { rect: Rect },
+    Wall { start: Vec2, end: Vec2 },
+}
+
+impl Obstacle {
+    fn segments(&self) -> Vec<(Vec2, Vec2)> {
+        // the straight edges of the obstacle, circles are ray cast separately
+        return match self {
+            Self::Circle { .. } => vec![],
+            Self::Rect { rect } => {
+                let corners = rect_corners(rect);
+                (0..4).map(|i| (corners[i], corners[(i + 1) % 4])).collect()
+            }
+            Self::Wall { start, end } => vec![(*start, *end)],
+        };
+    }
+
+    pub fn overlaps(&self, polygon: &[Vec2]) -> bool {
+        return match self {
+            Self::Circle { centre, radius } => {
+                // inside the polygon or close enough to one of its edges
+                let inside = polygons_overlap(polygon, &[*centre]);
+                let mut closest = f32::MAX;
+                for i in 0..polygon.len() {
+                    let a = polygon[i];
+                    let b = polygon[(i + 1) % polygon.len()];
+                    closest = closest.min(distance_to_segment(*centre, a, b));
+                }
+                inside || closest <= *radius
+            }
+            Self::Rect { rect } => polygons_overlap(polygon, &rect_corners(rect)),
+            Self::Wall { start, end } => polygons_overlap(polygon, &[*start, *end]),
+        };
+    }
+
+    pub fn draw(&self) {
+        match self {
+            Self::Circle { centre, radius } => {
+                draw_circle(centre.x, centre.y, *radius, OBSTACLE_COLOUR)
+            }
+            Self::Rect { rect } => draw_rectangle(rect.x, rect.y, rect.w, rect.h, OBSTACLE_COLOUR),
+            Self::Wall { start, end } => {
+                draw_line(start.x, start.y, end.x, end.y, 6.0, OBSTACLE_COLOUR)
+            }
+        }
+    }
+}
+
+// what the car is driving on
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Surface {
+    Tarmac,
+    Kerb,
+    Gravel,
+    Grass,
+}
+
+// what happens to a car when it leaves the track
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OffTrackMode {
+    Crash,    // the car crashes straight away
+    Surfaces, // the car carries on but the grass and gravel slow it down
+}
+
+impl OffTrackMode {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        return match name {
+            "crash" => Ok(Self::Crash),
+            "surfaces" => Ok(Self::Surfaces),
+            _ => Err("expected crash or surfaces".to_string()),
+        };
+    }
+
+    pub fn name(&self) -> &str {
+        return match self {
+            Self::Crash => "crash",
+            Self::Surfaces => "surfaces",
+        };
+    }
+}
+
+// where a point is relative to the centreline
+#[derive(Clone, Copy, Default)]
+pub struct TrackPosition {
+    pub segment: usize,
+    pub sector: usize, // the control segment it is in, sectors don't change with smoothing
+    pub t: f32,        // how far along the segment, between 0-1
+    pub distance_along: f32, // from the first centreline point
+    pub offset: f32,   // signed distance from the centreline, positive is left
+    pub distance: f32, // distance to the closest point on the centreline
+}
+
+// how many of the car's wheels have to leave the track for it to count as off
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OffTrackRule {
+    Centre,    // only the middle of the car is checked
+    AnyWheel,  // off as soon as one wheel leaves the track
+    TwoWheels, // off when half the car is off
+    AllWheels, // off only when all four wheels are off
+}
+
+impl OffTrackRule {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        return match name {
+            "centre" => Ok(Self::Centre),
+            "any_wheel" => Ok(Self::AnyWheel),
+            "two_wheels" => Ok(Self::TwoWheels),
+            "all_wheels" => Ok(Self::AllWheels),
+            _ => Err("expected centre, any_wheel, two_wheels or all_wheels".to_string()),
+        };
+    }
+
+    pub fn name(&self) -> &str {
+        return match self {
+            Self::Centre => "centre",
+            Self::AnyWheel => "any_wheel",
+            Self::TwoWheels => "two_wheels",
+            Self::AllWheels => "all_wheels",
+        };
+    }
+
+    pub fn is_off(&self, wheels_off: usize) -> bool {
+        // wheels_off is out of four, not used for the centre rule
+        return match self {
+            Self::Centre => wheels_off > 0,
+            Self::AnyWheel => wheels_off >= 1,
+            Self::TwoWheels => wheels_off >= 2,
+            Self::AllWheels => wheels_off >= 4,
+        };
+    }
 }
 
 impl Track {
-    pub fn new(points_set: [Vec2; 20], track_width: f32) -> Self {
+    pub fn new(points_set: &[Vec2], track_width: f32) -> Self {
+        let widths = vec![track_width; points_set.len()];
+        return Self::with_widths(points_set, &widths);
+    }
+
+    pub fn with_widths(points_set: &[Vec2], widths: &[f32]) -> Self {
+        let mut track = Self::without_geometry(points_set, widths);
+        track.build_geometry();
+        return track;
+    }
+
+    fn without_geometry(points_set: &[Vec2], widths: &[f32]) -> Self {
+        // everything but the edges, grid and lengths, which need build_geometry
+        if points_set.len() != widths.len() || points_set.len() < 3 {
+            panic!("[track.rs] - A track needs at least 3 points and one width per point!\n");
+        }
+
         return Self {
-            points_set,
-            track_width,
+            name: DEFAULT_TRACK_NAME.to_string(),
+            control_points: points_set.to_vec(),
+            control_widths: widths.to_vec(),
+            points_set: points_set.to_vec(),
+            resolution: 1,
+            widths: widths.to_vec(),
+            left_boundary: vec![],
+            right_boundary: vec![],
+            boundary_grid: SegmentGrid::new(vec![], GRID_CELL_SIZE),
+            cumulative_lengths: vec![],
+            total_length: 0.0,
+            start_distance: 0.0,
+            gravel_traps: vec![],
+            obstacles: vec![],
+        };
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        // track files have one centreline point per line in the form:
+        // x, y, width
+        // other features start with a keyword:
+        // gravel, x, y, radius
+        // circle, x, y, radius
+        // rect, x, y, width, height
+        // wall, x1, y1, x2, y2
+        // blank lines and lines starting with # are ignored
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+        let mut points: Vec<Vec2> = vec![];
+        let mut widths: Vec<f32> = vec![];
+        let mut gravel_traps: Vec<(Vec2, f32)> = vec![];
+        let mut obstacles: Vec<Obstacle> = vec![];
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("{}:{}: {}", path, line_number + 1, message);
+
+            // lines starting with a number are centreline points
+            let mut parts: Vec<&str> = line.split(',').map(|v| v.trim()).collect();
+            let keyword = if parts[0].parse::<f32>().is_ok() {
+                "point"
+            } else {
+                parts.remove(0)
+            };
+
+            let values: Vec<f32> = parts
+                .iter()
+                .map(|v| v.parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|e| error(e.to_string()))?;
+
+            // widths and sizes have to be above 0 for the collision and ray checks
+            let size = |name: &str, value: f32| -> Result<f32, String> {
+                if value.is_nan() || value <= 0.0 {
+                    return Err(error(format!("{} must be above 0, got {}", name, value)));
+                }
+                return Ok(value);
+            };
+
+            match (keyword, values.len()) {
+                ("point", 3) => {
+                    let width = size("width", values[2])?;
+                    points.push(vec2(values[0], values[1]));
+                    widths.push(width);
+                }
+                ("gravel", 3) => {
+                    let radius = size("radius", values[2])?;
+                    gravel_traps.push((vec2(values[0], values[1]), radius));
+                }
+                ("circle", 3) => obstacles.push(Obstacle::Circle {
+                    centre: vec2(values[0], values[1]),
+                    radius: size("radius", values[2])?,
+                }),
+                ("rect", 4) => obstacles.push(Obstacle::Rect {
+                    rect: Rect::new(
+                        values[0],
+                        values[1],
+                        size("width", values[2])?,
+                        size("height", values[3])?,
+                    ),
+                }),
+                ("wall", 4) => obstacles.push(Obstacle::Wall {
+                    start: vec2(values[0], values[1]),
+                    end: vec2(values[2], values[3]),
+                }),
+                ("point", _) => return Err(error("expected 'x, y, width'".to_string())),
+                ("gravel", _) => return Err(error("expected 'gravel, x, y, radius'".to_string())),
+                ("circle", _) => return Err(error("expected 'circle, x, y, radius'".to_string())),
+                ("rect", _) => {
+                    return Err(error("expected 'rect, x, y, width, height'".to_string()))
+                }
+                ("wall", _) => return Err(error("expected 'wall, x1, y1, x2, y2'".to_string())),
+                _ => return Err(error(format!("unknown keyword '{}'", keyword))),
+            }
+        }
+
+        if points.len() < 3 {
+            return Err(format!("{}: a track needs at least 3 points", path));
+        }
+
+        // built once everything is added, the obstacles go in the ray casting grid too
+        let mut track = Self::without_geometry(&points, &widths);
+        track.name = track_name_from_path(path);
+        track.gravel_traps = gravel_traps;
+        track.obstacles = obstacles;
+        track.build_geometry();
+        return Ok(track);
+    }
+
+    pub fn by_name(name: &str) -> Result<Self, String> {
+        // the built in track, or a track file from the tracks folder
+        if name == DEFAULT_TRACK_NAME {
+            return Ok(Self::new(&TEST_TRACK1, 100.0));
+        }
+        return Self::load(&format!("{}/{}.{}", TRACKS_FOLDER, name, TRACK_EXTENSION));
+    }
+
+    pub fn available_tracks() -> Vec<String> {
+        // built in track first, then any track files found
+        let mut names = vec![DEFAULT_TRACK_NAME.to_string()];
+
+        if let Ok(entries) = fs::read_dir(TRACKS_FOLDER) {
+            let mut found: Vec<String> = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == TRACK_EXTENSION))
+                .map(|path| track_name_from_path(&path.to_string_lossy()))
+                .filter(|name| name != DEFAULT_TRACK_NAME)
+                .collect();
+            found.sort();
+            names.extend(found);
+        }
+
+        return names;
+    }
+
+    pub fn smoothed(mut self, resolution: usize) -> Self {
+        // resample the centreline with a closed catmull-rom spline
+        // resolution is the number of points generated per control segment
+        // so a resolution of 1 leaves the track as straight segments
+        let resolution = resolution.max(1);
+        let n = self.control_points.len();
+        let mut points: Vec<Vec2> = vec![];
+        let mut widths: Vec<f32> = vec![];
+
+        for i in 0..n {
+            let p0 = self.control_points[(i + n - 1) % n];
+            let p1 = self.control_points[i];
+            let p2 = self.control_points[(i + 1) % n];
+            let p3 = self.control_points[(i + 2) % n];
+            let w1 = self.control_widths[i];
+            let w2 = self.control_widths[(i + 1) % n];
+
+            for step in 0..resolution {
+                let t = step as f32 / resolution as f32;
+                points.push(catmull_rom(p0, p1, p2, p3, t));
+                widths.push(lerp(w1, w2, t));
+            }
+        }
+
+        // start the polyline half way along the first control segment, the start
+        // line goes there too (see build_geometry)
+        points.rotate_left(resolution / 2);
+        widths.rotate_left(resolution / 2);
+
+        self.points_set = points;
+        self.resolution = resolution;
+        self.widths = widths;
+        self.build_geometry();
+        return self;
+    }
+
+    fn build_geometry(&mut self) {
+        // offset each centreline point by half the width along the average
+        // normal of the two segments either side of it
+        let points = &self.points_set;
+        let n = points.len();
+        self.left_boundary.clear();
+        self.right_boundary.clear();
+
+        for i in 0..n {
+            let prev = points[(i + n - 1) % n];
+            let point = points[i];
+            let next = points[(i + 1) % n];
+
+            let direction1 = point - prev;
+            let direction2 = next - point;
+            let normal1 = vec2(-direction1.y, direction1.x);
+            let normal2 = vec2(-direction2.y, direction2.x);
+            let avg_normal = ((normal1 + normal2) / 2.0).normalize();
+
+            let half_width = self.widths[i] / 2.0;
+            self.left_boundary.push(point + avg_normal * half_width);
+            self.right_boundary.push(point - avg_normal * half_width);
+        }
+
+        // both edges and any obstacles go into one grid for ray casting
+        let mut segments: Vec<(Vec2, Vec2)> = vec![];
+        for boundary in [&self.left_boundary, &self.right_boundary] {
+            for i in 0..n {
+                segments.push((boundary[i], boundary[(i + 1) % n]));
+            }
+        }
+        for obstacle in self.obstacles.iter() {
+            segments.extend(obstacle.segments());
+        }
+        self.boundary_grid = SegmentGrid::new(segments, GRID_CELL_SIZE);
+
+        // lengths along the centreline for working out progress
+        self.cumulative_lengths.clear();
+        self.total_length = 0.0;
+        for i in 0..n {
+            self.cumulative_lengths.push(self.total_length);
+            self.total_length += points[i].distance(points[(i + 1) % n]);
+        }
+
+        // the start line is half way along the first control segment, for even resolutions
+        // that's the first point and for odd ones the middle of the first segment
+        // so a resolution of 1 keeps the start of the unsmoothed track
+        self.start_distance = 0.0;
+        if self.resolution % 2 == 1 {
+            self.start_distance = points[0].distance(points[1]) / 2.0;
+        }
+    }
+
+    pub fn get_total_length(&self) -> f32 {
+        return self.total_length;
+    }
+
+    pub fn distance_between(&self, from: f32, to: f32) -> f32 {
+        // signed distance along the centreline between two distances, the short way
+        // round so crossing the start line doesn't jump by a lap
+        let delta = (to - from).rem_euclid(self.total_length);
+        if delta > self.total_length / 2.0 {
+            return delta - self.total_length;
+        }
+        return delta;
+    }
+
+    pub fn get_start_distance(&self) -> f32 {
+        return self.start_distance;
+    }
+
+    fn segment_at_distance(&self, distance: f32) -> (usize, f32) {
+        // which segment is the given distance along the centreline, and how far along it
+        let distance = distance.rem_euclid(self.total_length);
+        let segment = match self
+            .cumulative_lengths
+            .binary_search_by(|length| length.total_cmp(&distance))
+        {
+            Ok(i) => i,
+            Err(i) => i - 1, // the first length is 0 so i is at least 1
         };
+        return (segment, distance - self.cumulative_lengths[segment]);
+    }
+
+    pub fn point_at_distance(&self, distance: f32) -> Vec2 {
+        // the point on the centreline the given distance from the first point
+        let (segment, along) = self.segment_at_distance(distance);
+        let p1 = self.points_set[segment];
+        let p2 = self.points_set[(segment + 1) % self.points_set.len()];
+        return p1 + (p2 - p1).normalize_or_zero() * along;
+    }
+
+    pub fn direction_at_distance(&self, distance: f32) -> Vec2 {
+        // the direction the centreline is heading at the given distance
+        let (segment, _) = self.segment_at_distance(distance);
+        let p1 = self.points_set[segment];
+        let p2 = self.points_set[(segment + 1) % self.points_set.len()];
+        return (p2 - p1).normalize_or_zero();
+    }
+
+    fn project_onto_segment(&self, segment: usize, point: Vec2) -> TrackPosition {
+        let p1 = self.points_set[segment];
+        let p2 = self.points_set[(segment + 1) % self.points_set.len()];
+
+        let along = p2 - p1;
+        let t = closest_t_on_segment(point, p1, p2);
+        let closest = p1 + along * t;
+
+        // the left side of the track is along the normal (-y, x)
+        let normal = vec2(-along.y, along.x).normalize_or_zero();
+
+        return TrackPosition {
+            segment,
+            sector: self.sector_at(segment),
+            t,
+            distance_along: self.cumulative_lengths[segment] + along.length() * t,
+            offset: (point - closest).dot(normal),
+            distance: point.distance(closest),
+        };
+    }
+
+    pub fn project(&self, point: Vec2, previous_segment: Option<usize>) -> TrackPosition {
+        // follow a car round the centreline, well off the track the closest bit can be
+        // another part of it (across a hairpin) so it only moves as far as a car could drive
+        return self.closest_position(point, previous_segment, PROJECTION_MAX_JUMP);
+    }
+
+    fn closest_position(
+        &self,
+        point: Vec2,
+        previous_segment: Option<usize>,
+        max_jump: f32,
+    ) -> TrackPosition {
+        // find the closest point on the centreline to the given point
+        // if we know which segment it was on before only look near that one
+        let n = self.points_set.len();
+
+        let search = |segments: &mut dyn Iterator<Item = usize>| -> TrackPosition {
+            let mut best = TrackPosition {
+                distance: f32::MAX,
+                ..Default::default()
+            };
+            for segment in segments {
+                let position = self.project_onto_segment(segment, point);
+                if position.distance < best.distance {
+                    best = position;
+                }
+            }
+            return best;
+        };
+
+        if let Some(previous) = previous_segment {
+            let range = PROJECTION_SEARCH_RANGE.min(n / 2);
+            let mut nearby = (0..=range * 2).map(|i| (previous + n + i - range) % n);
+            let position = search(&mut nearby);
+
+            // only trust the local search if the point is close to the track
+            let width = self.width_at(position.segment, position.t);
+            if position.distance <= width {
+                return position;
+            }
+
+            // otherwise the closest of all, unless that's too far along from where it was
+            let closest = search(&mut (0..n));
+            let jump = self.distance_between(position.distance_along, closest.distance_along);
+            if jump.abs() > max_jump {
+                return position;
+            }
+            return closest;
+        }
+
+        return search(&mut (0..n));
+    }
+
+    pub fn surface_at(&self, point: Vec2, previous_segment: Option<usize>) -> Surface {
+        // whatever is closest, even if that's another part of the track
+        let position = self.closest_position(point, previous_segment, f32::MAX);
+        let half_width = self.width_at(position.segment, position.t) / 2.0;
+
+        if position.distance <= half_width {
+            return Surface::Tarmac;
+        }
+        if position.distance <= half_width + KERB_WIDTH {
+            return Surface::Kerb;
+        }
+        for (centre, radius) in self.gravel_traps.iter() {
+            if point.distance(*centre) <= *radius {
+                return Surface::Gravel;
+            }
+        }
+        return Surface::Grass;
+    }
+
+    pub fn is_point_on_track(&self, point: Vec2, previous_segment: Option<usize>) -> bool {
+        // on the track if within half the width of the nearest bit of centreline
+        let position = self.closest_position(point, previous_segment, f32::MAX);
+        return position.distance <= self.width_at(position.segment, position.t) / 2.0;
+    }
+
+    pub fn get_left_boundary(&self) -> &[Vec2] {
+        return &self.left_boundary;
+    }
+
+    pub fn get_right_boundary(&self) -> &[Vec2] {
+        return &self.right_boundary;
+    }
+
+    pub fn cast_ray(&self, origin: Vec2, direction: Vec2, max_distance: f32) -> f32 {
+        // distance to the nearest track edge or obstacle
+        // or max_distance if nothing is hit
+        let direction = direction.normalize();
+        let mut distance = self
+            .boundary_grid
+            .cast_ray(origin, direction, max_distance)
+            .unwrap_or(max_distance);
+
+        // circles aren't in the grid so check them directly
+        for obstacle in self.obstacles.iter() {
+            if let Obstacle::Circle { centre, radius } = obstacle {
+                if let Some(t) = ray_circle_intersection(origin, direction, *centre, *radius) {
+                    distance = distance.min(t);
+                }
+            }
+        }
+
+        return distance;
+    }
+
+    pub fn hits_obstacle(&self, polygon: &[Vec2]) -> bool {
+        return self
+            .obstacles
+            .iter()
+            .any(|obstacle| obstacle.overlaps(polygon));
     }
 
     pub fn draw(&self) {
+        // gravel goes underneath everything else
+        for (centre, radius) in self.gravel_traps.iter() {
+            draw_circle(centre.x, centre.y, *radius, GRAVEL_COLOUR);
+        }
+
+        // kerbs are drawn along both edges, the tarmac then covers the inside half
+        self.draw_kerbs(&self.left_boundary);
+        self.draw_kerbs(&self.right_boundary);
+
         for i in 0..self.points_set.len() {
             let p1 = self.points_set[i];
             let p2 = self.points_set[(i + 1) % self.points_set.len()];
+            let w1 = self.widths[i];
+            let w2 = self.widths[(i + 1) % self.widths.len()];
 
-            let mp = (p1 + p2) / 2.0;
+            draw_tapered_line(p1, p2, w1, w2, TARMAC_COLOUR);
+        }
 
-            draw_thick_line(p1.x, p1.y, p2.x, p2.y, self.track_width, tarmac_colour);
-            if i == 0 {
-                draw_circle(mp.x, mp.y, 8.0, WHITE);
-            }
+        // draw the start line marker
+        let start = self.get_start_pos();
+        draw_circle(start.x, start.y, 8.0, WHITE);
+
+        for obstacle in self.obstacles.iter() {
+            obstacle.draw();
         }
+
         // draw the checkpoints
         self.draw_checkpoints();
     }
 
-    pub fn get_points(&self) -> &[Vec2; 20] {
+    fn draw_kerbs(&self, boundary: &[Vec2]) {
+        // red and white stripes, the stripe pattern carries on between segments
+        let mut distance: f32 = 0.0;
+        for i in 0..boundary.len() {
+            let p1 = boundary[i];
+            let p2 = boundary[(i + 1) % boundary.len()];
+            let length = p1.distance(p2);
+
+            let mut along: f32 = 0.0;
+            while along < length {
+                let stripe = ((distance + along) / KERB_STRIPE_LENGTH) as usize % 2;
+                let pos = p1 + (p2 - p1) * (along / length);
+                draw_circle(pos.x, pos.y, KERB_WIDTH, KERB_COLOURS[stripe]);
+                along += KERB_WIDTH / 2.0;
+            }
+            distance += length;
+        }
+    }
+
+    pub fn get_resolution(&self) -> usize {
+        return self.resolution;
+    }
+
+    pub fn sector_count(&self) -> usize {
+        // one sector per control segment however smooth the centreline is
+        return self.control_points.len();
+    }
+
+    pub fn sector_at(&self, segment: usize) -> usize {
+        // undo the rotation done when smoothing to find the control segment
+        let unrotated = (segment + self.resolution / 2) % self.points_set.len();
+        return unrotated / self.resolution;
+    }
+
+    pub fn get_points(&self) -> &[Vec2] {
         return &self.points_set;
     }
 
-    pub fn get_width(&self) -> f32 {
-        return self.track_width;
+    pub fn get_widths(&self) -> &[f32] {
+        return &self.widths;
+    }
+
+    pub fn width_at(&self, segment: usize, t: f32) -> f32 {
+        // width part way along a segment, t between 0-1
+        let w1 = self.widths[segment % self.widths.len()];
+        let w2 = self.widths[(segment + 1) % self.widths.len()];
+        return lerp(w1, w2, t);
     }
 
     pub fn draw_checkpoints(&self) {
-        for i in 0..self.points_set.len() {
-            let p1 = self.points_set[i];
-            let p2 = self.points_set[(i + 1) % self.points_set.len()]; // this is the one we drawing on
-            let p3 = self.points_set[(i + 2) % self.points_set.len()];
+        // a checkpoint goes across the track at the start of every sector
+        let n = self.points_set.len();
+        for i in 0..n {
+            if self.sector_at(i) == self.sector_at((i + n - 1) % n) {
+                continue;
+            }
+            let left = self.left_boundary[i];
+            let right = self.right_boundary[i];
 
-            let joining_vec1 = p2 - p1;
-            let normal1 = vec2(-joining_vec1.y, joining_vec1.x);
+            draw_line(left.x, left.y, right.x, right.y, 4.0, CHECKPOINT_COLOUR);
+        }
+    }
 
-            let joining_vec2 = p3 - p2;
-            let normal2 = vec2(-joining_vec2.y, joining_vec2.x);
+    pub fn get_grid_slots(&self, count: usize) -> Vec<(Vec2, f32)> {
+        // centre and angle of each place on the grid, pole first
+        // two cars a row behind the start line, the right hand car half a row further back
+        let mut slots = vec![];
+        for i in 0..count {
+            let mut back = GRID_FIRST_GAP + GRID_ROW_SPACING * (i / 2) as f32;
+            let mut side = 1.0;
+            if i % 2 == 1 {
+                back += GRID_ROW_SPACING / 2.0;
+                side = -1.0;
+            }
 
-            let avg_normal = (normal1 + normal2) / 2.0;
+            let distance = self.start_distance - back;
+            let centre = self.point_at_distance(distance);
+            let direction = self.direction_at_distance(distance);
 
-            let checkpoint_vec = avg_normal.normalize() * self.track_width;
-            let start_pos = p2;
+            // a quarter of the way across, but never hanging off the edge
+            let (segment, along) = self.segment_at_distance(distance);
+            let next = self.points_set[(segment + 1) % self.points_set.len()];
+            let length = self.points_set[segment].distance(next);
+            let width = self.width_at(segment, along / length.max(f32::EPSILON));
+            let offset = (width / 4.0)
+                .min((width - Car::HITBOX_WIDTH) / 2.0)
+                .max(0.0);
 
-            draw_line(
-                start_pos.x - checkpoint_vec.x * 0.5,
-                start_pos.y - checkpoint_vec.y * 0.5,
-                start_pos.x + checkpoint_vec.x * 0.5,
-                start_pos.y + checkpoint_vec.y * 0.5,
-                4.0,
-                checkpoint_colour,
-            );
+            let position = centre + direction.perp() * offset * side;
+            slots.push((position, direction.y.atan2(direction.x)));
         }
+        return slots;
     }
 
     pub fn get_start_pos(&self) -> Vec2 {
-        let pos = self.points_set[0];
-        let pos1 = self.points_set[1];
+        return self.point_at_distance(self.start_distance);
+    }
+}
+
+fn rect_corners(rect: &Rect) -> [Vec2; 4] {
+    return [
+        vec2(rect.x, rect.y),
+        vec2(rect.x + rect.w, rect.y),
+        vec2(rect.x + rect.w, rect.y + rect.h),
+        vec2(rect.x, rect.y + rect.h),
+    ];
+}
+
+fn track_name_from_path(path: &str) -> String {
+    // tracks are named after their file, e.g. tracks/chicane.track -> chicane
+    return std::path::Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square_track() -> Track {
+        // only vertical and horizontal segments
+        let points = [
+            vec2(100.0, 100.0),
+            vec2(500.0, 100.0),
+            vec2(500.0, 500.0),
+            vec2(100.0, 500.0),
+        ];
+        return Track::new(&points, 50.0);
+    }
+
+    #[test]
+    fn point_near_horizontal_segment() {
+        let track = square_track();
+        assert!(track.is_point_on_track(vec2(300.0, 120.0), None));
+        assert!(track.is_point_on_track(vec2(300.0, 80.0), None));
+        assert!(!track.is_point_on_track(vec2(300.0, 130.0), None));
+        assert!(!track.is_point_on_track(vec2(300.0, 70.0), None));
+    }
+
+    #[test]
+    fn point_near_vertical_segment() {
+        let track = square_track();
+        assert!(track.is_point_on_track(vec2(520.0, 300.0), None));
+        assert!(track.is_point_on_track(vec2(480.0, 300.0), None));
+        assert!(!track.is_point_on_track(vec2(530.0, 300.0), None));
+        assert!(!track.is_point_on_track(vec2(300.0, 300.0), None));
+    }
+
+    #[test]
+    fn point_past_end_of_segment_is_off() {
+        // in line with the top segment but beyond its end, the old infinite
+        // line check would have called this on track
+        let track = square_track();
+        assert!(!track.is_point_on_track(vec2(600.0, 100.0), None));
+    }
+
+    #[test]
+    fn projection_finds_segment_and_side() {
+        let track = square_track();
+        let position = track.project(vec2(500.0 - 10.0, 300.0), Some(1));
+        assert_eq!(position.segment, 1);
+        assert!((position.t - 0.5).abs() < 1e-5);
+        assert!((position.distance - 10.0).abs() < 1e-5);
+        // inside of a clockwise (on screen) loop is to the left
+        assert!(position.offset > 0.0);
+    }
+
+    #[test]
+    fn projection_doesnt_jump_across_a_hairpin() {
+        // two straights 120px apart joined at the ends
+        let points = [
+            vec2(100.0, 100.0),
+            vec2(200.0, 100.0),
+            vec2(300.0, 100.0),
+            vec2(400.0, 100.0),
+            vec2(500.0, 100.0),
+            vec2(600.0, 160.0),
+            vec2(500.0, 220.0),
+            vec2(400.0, 220.0),
+            vec2(300.0, 220.0),
+            vec2(200.0, 220.0),
+            vec2(100.0, 220.0),
+            vec2(0.0, 160.0),
+        ];
+        let track = Track::new(&points, 50.0).smoothed(8);
+        let top = track.project(vec2(250.0, 100.0), None);
+        let bottom = track.project(vec2(250.0, 220.0), None);
+
+        // a car off the top straight and nearly on the bottom one stays on the top one
+        let point = vec2(250.0, 200.0);
+        let position = track.project(point, Some(top.segment));
+        assert_eq!(position.sector, top.sector);
+        assert!(
+            track
+                .distance_between(top.distance_along, position.distance_along)
+                .abs()
+                < 1e-2
+        );
+        assert!((position.distance - 100.0).abs() < 1e-2);
+
+        // coming from the bottom straight it's on that one
+        let position = track.project(point, Some(bottom.segment));
+        assert_eq!(position.sector, bottom.sector);
+        assert!((position.distance - 20.0).abs() < 1e-2);
+
+        // and either way it is driving on the bottom straight's tarmac
+        assert!(track.is_point_on_track(point, Some(top.segment)));
+        assert_eq!(track.surface_at(point, Some(top.segment)), Surface::Tarmac);
+    }
+
+    #[test]
+    fn off_track_rules() {
+        assert!(!OffTrackRule::AllWheels.is_off(3));
+        assert!(OffTrackRule::AllWheels.is_off(4));
+        assert!(OffTrackRule::AnyWheel.is_off(1));
+        assert!(!OffTrackRule::TwoWheels.is_off(1));
+        assert!(OffTrackRule::TwoWheels.is_off(2));
+        assert_eq!(
+            OffTrackRule::from_name("two_wheels"),
+            Ok(OffTrackRule::TwoWheels)
+        );
+        assert!(OffTrackRule::from_name("wheels").is_err());
+    }
+
+    #[test]
+    fn smoothed_track_keeps_its_points_and_start() {
+        let track = square_track();
+        let smoothed = square_track().smoothed(4);
+        assert_eq!(smoothed.get_points().len(), 16);
+        assert_eq!(smoothed.get_resolution(), 4);
+        // every control point is still on the centreline
+        for point in track.get_points() {
+            assert!(smoothed
+                .get_points()
+                .iter()
+                .any(|p| p.distance(*point) < 1e-3));
+        }
+        // the polyline starts half way along the first control segment
+        let first = smoothed.get_points()[0];
+        assert!((first.x - 300.0).abs() < 1e-3);
+        // a resolution of 1 leaves it as straight segments
+        assert_eq!(square_track().smoothed(1).get_points(), track.get_points());
+    }
+
+    #[test]
+    fn start_stays_half_way_along_the_first_control_segment() {
+        // unsmoothed it's the middle of the first straight
+        let track = square_track();
+        assert!(track.get_start_pos().distance(vec2(300.0, 100.0)) < 1e-3);
+        assert!((track.get_start_distance() - 200.0).abs() < 1e-3);
+
+        // smoothed it's where the spline is half way along, the straight bows out to y = 50
+        let points = track.get_points();
+        let middle = catmull_rom(points[3], points[0], points[1], points[2], 0.5);
+        for resolution in 2..=9 {
+            let smoothed = square_track().smoothed(resolution);
+            let start = smoothed.get_start_pos();
+            assert!((start.x - 300.0).abs() < 1e-3, "{}: {}", resolution, start);
+            // odd resolutions sit on the chord either side of the middle
+            let allowed = if resolution % 2 == 0 {
+                1e-3
+            } else {
+                20.0 / resolution as f32
+            };
+            assert!(
+                start.distance(middle) < allowed,
+                "{}: {}",
+                resolution,
+                start
+            );
+            assert!(
+                smoothed
+                    .point_at_distance(smoothed.get_start_distance())
+                    .distance(start)
+                    < 1e-3
+            );
+        }
+    }
+
+    #[test]
+    fn sectors_stay_on_control_points() {
+        let track = square_track();
+        let smoothed = square_track().smoothed(4);
+        assert_eq!(smoothed.sector_count(), track.sector_count());
+        for segment in 0..track.get_points().len() {
+            assert_eq!(track.sector_at(segment), segment);
+        }
+
+        // the smoothed track starts half way along the first control segment
+        let sectors: Vec<usize> = (0..16).map(|i| smoothed.sector_at(i)).collect();
+        assert_eq!(sectors, [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 0, 0]);
+        let position = smoothed.project(vec2(500.0, 300.0), None);
+        assert_eq!(position.sector, 1);
+    }
+
+    #[test]
+    fn load_rejects_bad_widths() {
+        let path = std::env::temp_dir().join("racers_bad_width.track");
+        let path = path.to_string_lossy().to_string();
+        for width in ["0", "-20", "NaN"] {
+            let contents = format!("# bad\n100, 100, 50\n500, 100, {}\n500, 500, 50\n", width);
+            fs::write(&path, contents).unwrap();
+            let error = Track::load(&path).err().unwrap();
+            assert!(error.contains(":3:"), "{}", error);
+        }
+
+        fs::write(&path, "100, 100, 50\n500, 100, 40\n500, 500, 50\n").unwrap();
+        assert!(Track::load(&path).is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_rejects_bad_obstacle_sizes() {
+        let path = std::env::temp_dir().join("racers_bad_obstacle.track");
+        let path = path.to_string_lossy().to_string();
+        let points = "100, 100, 50\n500, 100, 50\n500, 500, 50\n";
+        for value in ["0", "-20", "NaN"] {
+            for feature in [
+                format!("gravel, 300, 300, {}", value),
+                format!("circle, 300, 300, {}", value),
+                format!("rect, 300, 300, {}, 40", value),
+                format!("rect, 300, 300, 40, {}", value),
+            ] {
+                fs::write(&path, format!("{}{}\n", points, feature)).unwrap();
+                let error = Track::load(&path).err().unwrap();
+                assert!(error.contains(":4:"), "{}: {}", feature, error);
+            }
+        }
+
+        let features = "gravel, 300, 300, 40\ncircle, 300, 300, 10\nrect, 300, 300, 40, 20\n";
+        fs::write(&path, format!("{}{}", points, features)).unwrap();
+        assert!(Track::load(&path).is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn grid_slots_line_up_behind_the_start_line() {
+        // odd resolutions start part way along a segment, even ones on a point
+        for resolution in [1, 3, 4] {
+            let track = square_track().smoothed(resolution);
+            let slots = track.get_grid_slots(4);
+            let mut last_back = 0.0;
+            for (i, (centre, angle)) in slots.into_iter().enumerate() {
+                // pole 50px behind the line then every car half a row further back
+                let expected = 50.0 + 45.0 * i as f32;
+                let position = track.project(centre, None);
+                let back =
+                    -track.distance_between(track.get_start_distance(), position.distance_along);
+                assert!(
+                    (back - expected).abs() < 2.0,
+                    "{} {}: {}",
+                    resolution,
+                    i,
+                    back
+                );
+                assert!(back > last_back);
+                last_back = back;
+
+                // facing along the track, alternating sides and not hanging off the edge
+                let direction = track.direction_at_distance(position.distance_along);
+                assert!((Vec2::from_angle(angle) - direction).length() < 0.1);
+                assert_eq!(position.offset > 0.0, i % 2 == 0, "{} {}", resolution, i);
+                assert!(position.offset.abs() + Car::HITBOX_WIDTH / 2.0 <= 25.0 + 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn reversing_over_the_line_goes_negative() {
+        // add up the distance driven like a car does, backwards from the start
+        let track = square_track();
+        let mut position = track.project(track.get_start_pos(), None);
+        let mut progress = 0.0;
+        for i in 1..=60 {
+            // back past the first centreline point where distance_along wraps round
+            let distance = track.get_start_distance() - i as f32 * 5.0;
+            let next = track.project(track.point_at_distance(distance), Some(position.segment));
+            progress += track.distance_between(position.distance_along, next.distance_along);
+            position = next;
+        }
+        assert!(position.distance_along > track.get_total_length() - 200.0);
+        assert!((progress + 300.0).abs() < 1e-2, "{}", progress);
 
-        return (pos + pos1) / 2.0;
+        // and forwards again goes back to zero
+        let back = track.project(track.get_start_pos(), Some(position.segment));
+        progress += track.distance_between(position.distance_along, back.distance_along);
+        assert!(progress.abs() < 1e-2);
     }
 }
@@ -36,7 +36,7 @@ impl Button {
     }
 
     pub fn check_pressed(&mut self) -> bool {
-        if (is_mouse_button_pressed(MouseButton::Left)) {
+        if is_mouse_button_pressed(MouseButton::Left) {
             let mp = mouse_position();
             if self.hitbox.contains(mp.into()) {
                 self.pressed = true;
@@ -46,6 +46,10 @@ impl Button {
         return false;
     }
 
+    pub fn set_text(&mut self, text: String) {
+        self.text = text;
+    }
+
     pub fn reset(&mut self) {
         self.pressed = false;
     }
@@ -64,14 +68,14 @@ impl Slider {
     }
 
     pub fn draw(&self) {
-        let width = 400.0; // sets length of slider to 400px
+        let width: f32 = 400.0; // sets length of slider to 400px
         let radius = 14.0; // sets radius of node to 5px
         let pos = self.pos;
 
         draw_line(pos.x, pos.y, pos.x + width, pos.y, 6.0, BLACK);
         let node_pos = Vec2::new(
             pos.x
-                + width as f32 * (self.value as f32 - self.min as f32)
+                + width * (self.value as f32 - self.min as f32)
                     / (self.max as f32 - self.min as f32),
             pos.y,
         );
@@ -80,7 +84,7 @@ impl Slider {
         draw_text(&format!("{}", self.min), pos.x - 25.0, pos.y, 20.0, BLACK);
         draw_text(
             &format!("{}", self.max),
-            pos.x + width as f32 + 15.0,
+            pos.x + width + 15.0,
             pos.y,
             20.0,
             BLACK,
@@ -97,29 +101,30 @@ impl Slider {
     pub fn update(&mut self) {
         let pos = self.pos;
         let radius = 14.0;
-        let width = 400.0;
+        let width: f32 = 400.0;
         let node_pos = Vec2::new(
             pos.x
-                + width as f32 * (self.value as f32 - self.min as f32)
+                + width * (self.value as f32 - self.min as f32)
                     / (self.max as f32 - self.min as f32),
             pos.y,
         );
         let mp = Vec2::from(mouse_position());
 
-        if !self.selected && is_mouse_button_down(MouseButton::Left) {
-            if (mp - node_pos).length() <= radius {
-                self.selected = true;
-            }
+        if !self.selected
+            && is_mouse_button_down(MouseButton::Left)
+            && (mp - node_pos).length() <= radius
+        {
+            self.selected = true;
         }
 
-        if (self.selected && is_mouse_button_released(MouseButton::Left)) {
+        if self.selected && is_mouse_button_released(MouseButton::Left) {
             self.selected = false;
         }
 
-        if (self.selected) {
+        if self.selected {
             // make node follow the mouse's x position
             // how many pixels is one value point worth
-            let pixels_per_unit: f32 = width as f32 / (self.max as f32 - self.min as f32);
+            let pixels_per_unit: f32 = width / (self.max as f32 - self.min as f32);
 
             let dx = mp.x - node_pos.x;
             let delta_value = dx / pixels_per_unit;
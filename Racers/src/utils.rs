@@ -10,6 +10,13 @@ pub fn lerp(val1: f32, val2: f32, weight: f32) -> f32 {
     return val1 + (val2 - val1) * clamp(weight, 0.0, 1.0);
 }
 
+pub fn gaussian(std_dev: f32) -> f32 {
+    // normally distributed random number with a mean of 0 (box-muller)
+    let u1: f32 = macroquad::rand::gen_range(f32::EPSILON, 1.0);
+    let u2: f32 = macroquad::rand::gen_range(0.0, 1.0);
+    return (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos() * std_dev;
+}
+
 pub fn draw_thick_line(x1: f32, y1: f32, x2: f32, y2: f32, thickness: f32, colour: Color) {
     // draw a set of circles along the line
     // the number of circles determines how smooth the line is so i chose 20
@@ -27,16 +34,43 @@ pub fn draw_thick_line(x1: f32, y1: f32, x2: f32, y2: f32, thickness: f32, colou
     }
 }
 
-pub fn find_line_eq(x1: f32, y1: f32, x2: f32, y2: f32) -> Vec2 {
-    // trying to complete form:
-    // ax + by + c = 0
-    let m = (y2 - y1) / (x2 - x1);
-    let d = y1 - m * x1;
-    let a = -m;
-    let c = -d;
+pub fn draw_tapered_line(p1: Vec2, p2: Vec2, thickness1: f32, thickness2: f32, colour: Color) {
+    // same as draw_thick_line but the thickness changes along the line
+    let steps = 20;
+    for step in 0..steps {
+        let t = step as f32 / steps as f32;
+        let pos = p1 + (p2 - p1) * t;
+        draw_circle(pos.x, pos.y, lerp(thickness1, thickness2, t) / 2.0, colour);
+    }
+}
+
+pub fn catmull_rom(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, t: f32) -> Vec2 {
+    // uniform catmull-rom spline, gives a point between p1 and p2
+    // t between 0-1
+    let t2 = t * t;
+    let t3 = t2 * t;
 
-    // the Vec2 will return (a, c) in this form
-    return vec2(a, c);
+    return 0.5
+        * ((2.0 * p1)
+            + (p2 - p0) * t
+            + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+            + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3);
+}
+
+pub fn closest_t_on_segment(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    // how far along a-b (between 0-1) the closest point to the given point is
+    let along = b - a;
+    let length_squared = along.length_squared();
+    if length_squared < f32::EPSILON {
+        // a and b are the same point
+        return 0.0;
+    }
+    return clamp((point - a).dot(along) / length_squared, 0.0, 1.0);
+}
+
+pub fn distance_to_segment(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let t = closest_t_on_segment(point, a, b);
+    return point.distance(a + (b - a) * t);
 }
 
 pub fn line_intersection(p1: Vec2, p2: Vec2, q1: Vec2, q2: Vec2) -> Option<Vec2> {
@@ -46,7 +80,7 @@ pub fn line_intersection(p1: Vec2, p2: Vec2, q1: Vec2, q2: Vec2) -> Option<Vec2>
     let u = -(((p1.x - p2.x) * (p1.y - q1.y) - (p1.y - p2.y) * (p1.x - q1.x))
         / ((p1.x - p2.x) * (q1.y - q2.y) - (p1.y - p2.y) * (q1.x - q2.x)));
 
-    if (0.0 <= t && t <= 1.0) && (0.0 <= u && u <= 1.0) {
+    if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
         let point_intersection = vec2(q1.x + u * (q2.x - q1.x), q1.y + u * (q2.y - q1.y));
         return Some(point_intersection);
     }
@@ -54,8 +88,247 @@ pub fn line_intersection(p1: Vec2, p2: Vec2, q1: Vec2, q2: Vec2) -> Option<Vec2>
     None
 }
 
+pub fn ray_segment_intersection(origin: Vec2, direction: Vec2, a: Vec2, b: Vec2) -> Option<f32> {
+    // returns how far along the ray it hits the segment a-b
+    let edge = b - a;
+    let denominator = direction.perp_dot(edge);
+    if denominator.abs() < f32::EPSILON {
+        // parallel so never hits
+        return None;
+    }
+
+    let to_a = a - origin;
+    let t = to_a.perp_dot(edge) / denominator; // along the ray
+    let u = to_a.perp_dot(direction) / denominator; // along the segment
+
+    if t >= 0.0 && (0.0..=1.0).contains(&u) {
+        return Some(t);
+    }
+
+    None
+}
+
+pub fn ray_circle_intersection(
+    origin: Vec2,
+    direction: Vec2,
+    centre: Vec2,
+    radius: f32,
+) -> Option<f32> {
+    // returns how far along the ray (direction normalised) it first hits the circle
+    let to_centre = centre - origin;
+    let along = to_centre.dot(direction);
+    let miss_distance_squared = to_centre.length_squared() - along * along;
+    let radius_squared = radius * radius;
+    if miss_distance_squared > radius_squared {
+        return None;
+    }
+
+    let half_chord = (radius_squared - miss_distance_squared).sqrt();
+    let t1 = along - half_chord;
+    let t2 = along + half_chord;
+    if t1 >= 0.0 {
+        return Some(t1);
+    }
+    if t2 >= 0.0 {
+        // started inside the circle
+        return Some(0.0);
+    }
+
+    None
+}
+
+pub fn polygons_overlap(polygon1: &[Vec2], polygon2: &[Vec2]) -> bool {
+    // separating axis test for two convex polygons (a segment counts as a polygon)
+    // if there is a gap between them along any edge normal they don't overlap
+    for polygon in [polygon1, polygon2] {
+        for i in 0..polygon.len() {
+            let edge = polygon[(i + 1) % polygon.len()] - polygon[i];
+            let axis = edge.perp();
+
+            let project = |points: &[Vec2]| -> (f32, f32) {
+                let mut min = f32::MAX;
+                let mut max = f32::MIN;
+                for point in points {
+                    let value = point.dot(axis);
+                    min = min.min(value);
+                    max = max.max(value);
+                }
+                return (min, max);
+            };
+
+            let (min1, max1) = project(polygon1);
+            let (min2, max2) = project(polygon2);
+            if max1 < min2 || max2 < min1 {
+                return false;
+            }
+        }
+    }
+    return true;
+}
+
+pub fn polygon_penetration(polygon1: &[Vec2], polygon2: &[Vec2]) -> Option<(Vec2, f32)> {
+    // same test as polygons_overlap but also finds the smallest push that separates them
+    // returns the direction to move polygon2 away from polygon1 and how far
+    let mut smallest = (Vec2::ZERO, f32::MAX);
+    for polygon in [polygon1, polygon2] {
+        for i in 0..polygon.len() {
+            let edge = polygon[(i + 1) % polygon.len()] - polygon[i];
+            let axis = edge.perp().normalize_or_zero();
+            if axis == Vec2::ZERO {
+                continue;
+            }
+
+            let project = |points: &[Vec2]| -> (f32, f32) {
+                let mut min = f32::MAX;
+                let mut max = f32::MIN;
+                for point in points {
+                    let value = point.dot(axis);
+                    min = min.min(value);
+                    max = max.max(value);
+                }
+                return (min, max);
+            };
+
+            let (min1, max1) = project(polygon1);
+            let (min2, max2) = project(polygon2);
+            if max1 < min2 || max2 < min1 {
+                return None;
+            }
+
+            // push whichever way is shorter
+            let forwards = max1 - min2;
+            let backwards = max2 - min1;
+            if forwards < smallest.1 {
+                smallest = (axis, forwards);
+            }
+            if backwards < smallest.1 {
+                smallest = (-axis, backwards);
+            }
+        }
+    }
+    return Some(smallest);
+}
+
 pub fn repeat_space(n: usize) {
-    for i in 0..n {
+    for _ in 0..n {
         print!(" ");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_to_vertical_segment() {
+        let a = vec2(10.0, 0.0);
+        let b = vec2(10.0, 100.0);
+        assert_eq!(distance_to_segment(vec2(15.0, 50.0), a, b), 5.0);
+        assert_eq!(distance_to_segment(vec2(10.0, 50.0), a, b), 0.0);
+        // past the end measures to the end point, not the infinite line
+        assert_eq!(distance_to_segment(vec2(10.0, 130.0), a, b), 30.0);
+        assert!(distance_to_segment(vec2(5.0, 20.0), a, b).is_finite());
+    }
+
+    #[test]
+    fn distance_to_horizontal_segment() {
+        let a = vec2(0.0, 10.0);
+        let b = vec2(100.0, 10.0);
+        assert_eq!(distance_to_segment(vec2(50.0, 4.0), a, b), 6.0);
+        assert_eq!(distance_to_segment(vec2(-3.0, 14.0), a, b), 5.0);
+    }
+
+    #[test]
+    fn distance_to_zero_length_segment() {
+        let a = vec2(20.0, 20.0);
+        assert_eq!(distance_to_segment(vec2(23.0, 24.0), a, a), 5.0);
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_control_points() {
+        let (p0, p1, p2, p3) = (
+            vec2(0.0, 0.0),
+            vec2(10.0, 5.0),
+            vec2(20.0, -5.0),
+            vec2(30.0, 0.0),
+        );
+        assert!(catmull_rom(p0, p1, p2, p3, 0.0).distance(p1) < 1e-5);
+        assert!(catmull_rom(p0, p1, p2, p3, 1.0).distance(p2) < 1e-5);
+    }
+
+    #[test]
+    fn catmull_rom_on_a_straight_line() {
+        // evenly spaced points in a line give evenly spaced points on the line
+        let p = |x: f32| vec2(x, 7.0);
+        let mid = catmull_rom(p(0.0), p(10.0), p(20.0), p(30.0), 0.5);
+        assert!(mid.distance(p(15.0)) < 1e-5);
+    }
+
+    fn square(x: f32, y: f32, size: f32) -> [Vec2; 4] {
+        return [
+            vec2(x, y),
+            vec2(x + size, y),
+            vec2(x + size, y + size),
+            vec2(x, y + size),
+        ];
+    }
+
+    #[test]
+    fn overlapping_polygons() {
+        let a = square(0.0, 0.0, 10.0);
+        assert!(polygons_overlap(&a, &square(5.0, 5.0, 10.0)));
+        assert!(!polygons_overlap(&a, &square(11.0, 0.0, 10.0)));
+        // touching edges count as overlapping
+        assert!(polygons_overlap(&a, &square(10.0, 0.0, 10.0)));
+        // a diamond whose bounding box overlaps but the shape doesn't
+        let diamond = [
+            vec2(20.0, 15.0),
+            vec2(25.0, 20.0),
+            vec2(20.0, 25.0),
+            vec2(15.0, 20.0),
+        ];
+        assert!(!polygons_overlap(&square(0.0, 0.0, 16.0), &diamond));
+        // a wall (segment) through the square, and a point inside it
+        assert!(polygons_overlap(&a, &[vec2(-5.0, 5.0), vec2(15.0, 5.0)]));
+        assert!(polygons_overlap(&a, &[vec2(3.0, 3.0)]));
+        assert!(!polygons_overlap(&a, &[vec2(-5.0, 20.0), vec2(15.0, 20.0)]));
+    }
+
+    #[test]
+    fn penetration_pushes_the_shortest_way() {
+        let a = square(0.0, 0.0, 10.0);
+        let (normal, depth) = polygon_penetration(&a, &square(8.0, 1.0, 10.0)).unwrap();
+        assert!((depth - 2.0).abs() < 1e-5);
+        // push the second square further right
+        assert!((normal - vec2(1.0, 0.0)).length() < 1e-5);
+
+        let (normal, depth) = polygon_penetration(&a, &square(1.0, -7.0, 10.0)).unwrap();
+        assert!((depth - 3.0).abs() < 1e-5);
+        assert!((normal - vec2(0.0, -1.0)).length() < 1e-5);
+
+        assert!(polygon_penetration(&a, &square(12.0, 0.0, 10.0)).is_none());
+    }
+
+    #[test]
+    fn rays_against_circles() {
+        let centre = vec2(10.0, 0.0);
+        let hit = ray_circle_intersection(Vec2::ZERO, vec2(1.0, 0.0), centre, 2.0);
+        assert!((hit.unwrap() - 8.0).abs() < 1e-5);
+        // pointing away, passing by, and starting inside
+        assert_eq!(
+            ray_circle_intersection(Vec2::ZERO, vec2(-1.0, 0.0), centre, 2.0),
+            None
+        );
+        assert_eq!(
+            ray_circle_intersection(Vec2::ZERO, vec2(0.0, 1.0), centre, 2.0),
+            None
+        );
+        assert_eq!(
+            ray_circle_intersection(centre, vec2(0.0, 1.0), centre, 2.0),
+            Some(0.0)
+        );
+        // just grazing the top
+        let grazing = ray_circle_intersection(vec2(0.0, 2.0), vec2(1.0, 0.0), centre, 2.0);
+        assert!((grazing.unwrap() - 10.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::car::Car;
+use crate::controller::Controls;
+use macroquad::prelude::*;
+
+// which physics moves the cars, picked per run so the two can be compared
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VehicleModel {
+    Arcade,  // the original model, the angle lerps towards the steering
+    Bicycle, // front and rear axles with tyre slip and weight transfer
+}
+
+impl VehicleModel {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        return match name {
+            "arcade" => Ok(Self::Arcade),
+            "bicycle" => Ok(Self::Bicycle),
+            _ => Err("expected arcade or bicycle".to_string()),
+        };
+    }
+
+    pub fn name(&self) -> &str {
+        return match self {
+            Self::Arcade => "arcade",
+            Self::Bicycle => "bicycle",
+        };
+    }
+
+    pub fn steer_for_curvature(&self, curvature: f32, speed: f32) -> f32 {
+        // the steering input (-1 to 1 is full lock) that follows a curve of the given curvature
+        return match self {
+            // turns at a rate set by the steering, so faster needs more lock
+            Self::Arcade => speed * curvature / ARCADE_TURN_RATE,
+            // the front wheel points along the curve, the same at any speed until the tyres slide
+            Self::Bicycle => (WHEELBASE * curvature).atan() / Car::MAX_TURNING_ANGLE,
+        };
+    }
+}
+
+// how quickly the arcade car's angle changes for a full steering input (rad/s)
+// the angle lerps towards angle + steer at 6 times a second
+const ARCADE_TURN_RATE: f32 = 6.0 * Car::STEER_WEIGHT;
+
+// bicycle model settings, lengths in px so they match the car's hitbox
+const WHEELBASE: f32 = Car::HITBOX_HEIGHT * 0.8;
+const CG_TO_FRONT: f32 = WHEELBASE * 0.45;
+const CG_TO_REAR: f32 = WHEELBASE - CG_TO_FRONT;
+const CG_HEIGHT: f32 = 10.0;
+const GRAVITY: f32 = 600.0; // px/s^2, sets how much grip the tyres have
+const INERTIA: f32 = Car::MASS
+    * (Car::HITBOX_WIDTH * Car::HITBOX_WIDTH + Car::HITBOX_HEIGHT * Car::HITBOX_HEIGHT)
+    / 12.0;
+
+// tyre curve, lateral force = grip * load * sin(C * atan(B * slip angle))
+// it builds up with slip, peaks and then falls away a little as the tyre slides
+const TYRE_STIFFNESS: f32 = 6.0; // B
+const TYRE_SHAPE: f32 = 1.6; // C
+
+// below this speed slip angles don't mean much so the wheels just roll where they point
+const KINEMATIC_SPEED: f32 = 60.0;
+// the tyre forces are stiff so the step is split up to keep it stable
+const MAX_SUBSTEP: f32 = 1.0 / 240.0;
+
+// the state the bicycle model keeps between ticks
+#[derive(Clone, Copy, Default)]
+pub struct Bicycle {
+    pub yaw_rate: f32,   // rad/s
+    pub long_accel: f32, // forward acceleration last step, for the weight transfer
+}
+
+impl Bicycle {
+    pub fn step(
+        &mut self,
+        velocity: &mut Vec2,
+        angle: &mut f32,
+        controls: Controls,
+        fric_coef: f32, // rolling friction, used the same way as the arcade model
+        grip: f32,      // tyre grip relative to tarmac
+        dt: f32,
+    ) {
+        let steps = (dt / MAX_SUBSTEP).ceil().max(1.0);
+        let h = dt / steps;
+        for _ in 0..steps as usize {
+            self.substep(velocity, angle, controls, fric_coef, grip, h);
+        }
+    }
+
+    fn substep(
+        &mut self,
+        velocity: &mut Vec2,
+        angle: &mut f32,
+        controls: Controls,
+        fric_coef: f32,
+        grip: f32,
+        dt: f32,
+    ) {
+        let forward = Vec2::from_angle(*angle);
+        let side = forward.perp();
+        let delta = controls.steer * Car::MAX_TURNING_ANGLE;
+
+        // velocity in the car's frame
+        let vx = velocity.dot(forward);
+        let vy = velocity.dot(side);
+
+        // driving, braking and rolling resistance along the car
+        let mut force_x = controls.throttle * Car::MAX_ACC * Car::MASS;
+        force_x -= vx * controls.brake * Car::BRAKING_FACTOR * Car::MASS;
+        force_x -= vx * fric_coef * Car::MASS;
+
+        if vx.abs() < KINEMATIC_SPEED {
+            // kinematic model, no sliding at low speed
+            self.yaw_rate = vx * delta.tan() / WHEELBASE;
+            self.long_accel = force_x / Car::MASS;
+
+            let vx = vx + self.long_accel * dt;
+            *angle += self.yaw_rate * dt;
+            *velocity = Vec2::from_angle(*angle) * vx;
+            return;
+        }
+
+        // braking moves weight onto the front, accelerating onto the rear
+        let weight = Car::MASS * GRAVITY;
+        let transfer = Car::MASS * self.long_accel * CG_HEIGHT / WHEELBASE;
+        let load_front = (weight * CG_TO_REAR / WHEELBASE - transfer).max(0.0);
+        let load_rear = (weight * CG_TO_FRONT / WHEELBASE + transfer).max(0.0);
+
+        // slip angles between where each axle points and where it's going
+        let slip_front = (vy + CG_TO_FRONT * self.yaw_rate).atan2(vx.abs()) - delta * vx.signum();
+        let slip_rear = (vy - CG_TO_REAR * self.yaw_rate).atan2(vx.abs());
+
+        let force_front = -tyre_force(slip_front, load_front * grip);
+        let force_rear = -tyre_force(slip_rear, load_rear * grip);
+
+        // forces along and across the car, the front one is turned with the wheels
+        let accel_x = (force_x - force_front * delta.sin()) / Car::MASS;
+        let accel_y = (force_rear + force_front * delta.cos()) / Car::MASS;
+        let yaw_accel =
+            (CG_TO_FRONT * force_front * delta.cos() - CG_TO_REAR * force_rear) / INERTIA;
+
+        self.long_accel = accel_x;
+        self.yaw_rate += yaw_accel * dt;
+        *velocity += (forward * accel_x + side * accel_y) * dt;
+        *angle += self.yaw_rate * dt;
+    }
+}
+
+fn tyre_force(slip_angle: f32, peak: f32) -> f32 {
+    return peak * (TYRE_SHAPE * (TYRE_STIFFNESS * slip_angle).atan()).sin();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bicycle_steering_follows_the_curve() {
+        // slow enough for the kinematic model, the yaw rate should be speed * curvature
+        let curvature = 1.0 / 200.0;
+        let steer = VehicleModel::Bicycle.steer_for_curvature(curvature, 40.0);
+        let mut bicycle = Bicycle::default();
+        let mut velocity = vec2(40.0, 0.0);
+        let mut angle = 0.0;
+        let controls = Controls {
+            throttle: 0.0,
+            steer,
+            brake: 0.0,
+        };
+        bicycle.step(&mut velocity, &mut angle, controls, 0.0, 1.0, MAX_SUBSTEP);
+        assert!((bicycle.yaw_rate - 40.0 * curvature).abs() < 1e-4);
+
+        // and it doesn't change with speed
+        let faster = VehicleModel::Bicycle.steer_for_curvature(curvature, 300.0);
+        assert_eq!(faster, steer);
+    }
+
+    #[test]
+    fn arcade_steering_grows_with_speed() {
+        let curvature = 1.0 / 200.0;
+        let slow = VehicleModel::Arcade.steer_for_curvature(curvature, 100.0);
+        let fast = VehicleModel::Arcade.steer_for_curvature(curvature, 200.0);
+        assert!((slow * ARCADE_TURN_RATE - 100.0 * curvature).abs() < 1e-6);
+        assert!((fast - slow * 2.0).abs() < 1e-6);
+        // the two models need different amounts of lock for the same curve
+        assert!((slow - VehicleModel::Bicycle.steer_for_curvature(curvature, 100.0)).abs() > 0.01);
+    }
+}